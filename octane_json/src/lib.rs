@@ -140,8 +140,8 @@ mod test {
         assert_eq!(*obj["a"].as_float().unwrap(), 1.0);
         assert_eq!(*obj["b"].as_string().unwrap(), "two".to_string());
         assert_eq!(*obj["c"].as_object().unwrap()["x"].as_integer().unwrap(), 3);
-        assert_eq!(*obj["d"].as_boolean().unwrap(), true);
-        assert_eq!(*obj["e"].as_boolean().unwrap(), false);
+        assert!(*obj["d"].as_boolean().unwrap());
+        assert!(!*obj["e"].as_boolean().unwrap());
         assert_eq!(obj["f"].as_null().unwrap(), ());
         assert!(obj["g"]
             .as_array()
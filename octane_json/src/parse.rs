@@ -50,17 +50,17 @@ pub fn parse_string(dat: &str) -> Option<(String, &str)> {
 }
 
 pub fn parse_bool(dat: &str) -> Option<(bool, &str)> {
-    if dat.starts_with("true") {
-        return Some((true, &dat[4..]));
-    } else if dat.starts_with("false") {
-        return Some((false, &dat[5..]));
+    if let Some(rest) = dat.strip_prefix("true") {
+        return Some((true, rest));
+    } else if let Some(rest) = dat.strip_prefix("false") {
+        return Some((false, rest));
     }
     None
 }
 
 pub fn parse_null(dat: &str) -> Option<((), &str)> {
-    if dat.starts_with("null") {
-        return Some(((), &dat[4..]));
+    if let Some(rest) = dat.strip_prefix("null") {
+        return Some(((), rest));
     }
     None
 }
@@ -95,19 +95,19 @@ pub fn parse_object(dat: &str) -> Option<(HashMap<String, Value>, &str)> {
     // This function assumes that the first character is {.
     let mut cur = consume_ws(&dat[1..]);
     let mut ret = HashMap::<String, Value>::new();
-    if *cur.as_bytes().get(0)? == b'}' {
+    if *cur.as_bytes().first()? == b'}' {
         return Some((ret, &cur[1..]));
     }
     while !cur.is_empty() {
         let (key, rest) = parse_string(cur)?;
         cur = consume_ws(rest);
-        if *cur.as_bytes().get(0)? != b':' {
+        if *cur.as_bytes().first()? != b':' {
             return None;
         }
         let (val, remainder) = parse_element(&cur[1..])?;
         ret.insert(key, val);
         cur = remainder;
-        match *cur.as_bytes().get(0)? {
+        match *cur.as_bytes().first()? {
             b',' => {
                 cur = consume_ws(&cur[1..]);
             }
@@ -122,13 +122,13 @@ pub fn parse_array(dat: &str) -> Option<(Vec<Value>, &str)> {
     // This function assumes that the first character is [.
     let mut cur = consume_ws(&dat[1..]);
     let mut ret = Vec::<Value>::new();
-    if *cur.as_bytes().get(0)? == b']' {
+    if *cur.as_bytes().first()? == b']' {
         return Some((ret, &cur[1..]));
     }
     while !cur.is_empty() {
         let (val, rest) = parse_element(cur)?;
         ret.push(val);
-        match *rest.as_bytes().get(0)? {
+        match *rest.as_bytes().first()? {
             b',' => {
                 cur = consume_ws(&rest[1..]);
             }
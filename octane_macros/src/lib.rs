@@ -24,7 +24,7 @@ pub fn status_codes(toks: TokenStream) -> TokenStream {
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use octane::prelude::*;
 ///
 /// #[derive(FromJSON, ToJSON)]
@@ -44,8 +44,10 @@ pub fn status_codes(toks: TokenStream) -> TokenStream {
 ///     }
 /// }
 ///
+/// # fn main() {
 /// let json_string = User::new().to_json_string().unwrap();
 /// let user_back: Option<User> = User::from_json_string(&json_string);
+/// # }
 /// ```
 #[proc_macro_derive(FromJSON)]
 pub fn derive_from_json(toks: TokenStream) -> TokenStream {
@@ -57,7 +59,7 @@ pub fn derive_from_json(toks: TokenStream) -> TokenStream {
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use octane::prelude::*;
 ///
 /// #[derive(ToJSON)]
@@ -77,7 +79,9 @@ pub fn derive_from_json(toks: TokenStream) -> TokenStream {
 ///     }
 /// }
 ///
+/// # fn main() {
 /// let json_string = User::new().to_json_string();
+/// # }
 /// ```
 #[proc_macro_derive(ToJSON)]
 pub fn derive_to_json(toks: TokenStream) -> TokenStream {
@@ -109,7 +113,7 @@ pub fn derive_to_json(toks: TokenStream) -> TokenStream {
 ///         ),
 ///     )?;
 ///
-///     app.listen(8080).await
+///     app.listen(8080, || {}).await
 /// }
 /// ```
 #[proc_macro_attribute]
@@ -117,14 +121,13 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let stream = StreamParser::new(item.into());
     let properties = stream.parse();
     let num_cpus = num_cpus::get() * 2;
-    let compile_error;
-    if properties.is_async {
-        compile_error = quote! {};
+    let compile_error = if properties.is_async {
+        quote! {}
     } else {
-        compile_error = quote! {
+        quote! {
             compile_error!("the async keyword is missing from function declaration");
         }
-    }
+    };
     let signature = properties.signature;
     let rest = properties.rest;
     let tokens = quote! {
@@ -133,6 +136,7 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
             let mut builder = tokio::runtime::Builder::new_multi_thread();
             builder
                 .enable_io()
+                .enable_time()
                 .thread_stack_size(10485760)
                 .thread_name("octane-main")
                 .worker_threads(#num_cpus);
@@ -156,14 +160,13 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
 pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let stream = StreamParser::new(item.into());
     let properties = stream.parse();
-    let compile_error;
-    if properties.is_async {
-        compile_error = quote! {};
+    let compile_error = if properties.is_async {
+        quote! {}
     } else {
-        compile_error = quote! {
+        quote! {
             compile_error!("the async keyword is missing from function declaration");
         }
-    }
+    };
     let signature = properties.signature;
     let rest = properties.rest;
     let tokens = quote! {
@@ -173,6 +176,7 @@ pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
             let mut builder = tokio::runtime::Builder::new_current_thread();
             builder
                 .enable_io()
+                .enable_time()
                 .thread_name("octane-test");
 
             let mut runtime = builder.build().expect("Unable to build tokio runtime");
@@ -192,8 +196,10 @@ pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
 ///
 /// # Example
 ///
-/// ```
-/// let path = path!("/templates");
+/// ```no_run
+/// use octane::prelude::*;
+///
+/// let path = path!("/src");
 /// ```
 ///
 /// This allows for safety, you cannot compile code that has
@@ -201,7 +207,7 @@ pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
 #[proc_macro]
 pub fn path(input: TokenStream) -> TokenStream {
     let input: Vec<TokenTree> = input.into_iter().collect();
-    let value = match &input.get(0) {
+    let value = match &input.first() {
         Some(TokenTree::Literal(literal)) => literal.to_string(),
         _ => panic!(),
     };
@@ -1,5 +1,7 @@
 use crate::default;
+use crate::responder::StatusCode;
 use core::time::Duration;
+use std::collections::HashMap;
 use std::path::PathBuf;
 #[cfg(feature = "rustls")]
 use tokio_rustls::rustls::{
@@ -80,6 +82,228 @@ impl Ssl {
     }
 }
 
+/// Limits struct holds the limits on the size of the requests,
+/// requests going over them are answered with an error instead
+/// of being read any further.
+///
+/// ```no_run
+/// use octane::Octane;
+/// use octane::config::Config;
+///
+/// let mut app = Octane::new();
+/// app.limits()
+///     .body_bytes(1024 * 1024)
+///     .header_count(50);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The most bytes the request line and the headers can take
+    /// up together, 16 KiB by default. Answered with a 431.
+    pub header_bytes: usize,
+    /// The most headers a request can have, 100 by default.
+    /// Answered with a 431.
+    pub header_count: usize,
+    /// The most bytes the body can take up, 10 MiB by default.
+    /// Chunked bodies are cut off as soon as a chunk would take
+    /// their decoded size over it, their size lines and trailers
+    /// are held to `header_bytes`. Answered with a 413.
+    pub body_bytes: usize,
+    /// The most bytes the request target can take up, 8 KiB by
+    /// default. Answered with a 414.
+    pub uri_length: usize,
+}
+
+impl Limits {
+    /// Returns a new Limits struct instance with the default
+    /// limits
+    pub fn new() -> Self {
+        Limits {
+            header_bytes: 16 * 1024,
+            header_count: 100,
+            body_bytes: 10 * 1024 * 1024,
+            uri_length: 8 * 1024,
+        }
+    }
+    /// Sets the most bytes the request line and headers can
+    /// take up together
+    pub fn header_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.header_bytes = bytes;
+        self
+    }
+    /// Sets the most headers a request can have
+    pub fn header_count(&mut self, count: usize) -> &mut Self {
+        self.header_count = count;
+        self
+    }
+    /// Sets the most bytes the body of a request can take up
+    pub fn body_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.body_bytes = bytes;
+        self
+    }
+    /// Sets the most bytes the request target can take up
+    pub fn uri_length(&mut self, length: usize) -> &mut Self {
+        self.uri_length = length;
+        self
+    }
+}
+
+/// Timeouts struct holds how long each part of handling a
+/// request can take, `None` means there is no limit. A client
+/// which is too slow to send the request gets a 408, one which
+/// is too slow to read the response is disconnected.
+///
+/// ```no_run
+/// use octane::Octane;
+/// use octane::config::Config;
+/// use std::time::Duration;
+///
+/// let mut app = Octane::new();
+/// app.timeouts()
+///     .header_read(Duration::from_secs(10))
+///     .handler(Duration::from_secs(30));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    /// The time the client has to send the request line and the
+    /// headers, 30 seconds by default. The connection is closed
+    /// silently if nothing was sent, a 408 is sent otherwise.
+    pub header_read: Option<Duration>,
+    /// The time the client has to send the body once the headers
+    /// are read, 60 seconds by default. Answered with a 408.
+    pub body_read: Option<Duration>,
+    /// The time the closures have to handle the request, there is
+    /// no limit by default. Answered with a 503.
+    pub handler: Option<Duration>,
+    /// The time sending the response can take, there is no limit
+    /// by default. The connection is closed when it runs out.
+    pub write: Option<Duration>,
+    /// The time the requests which are being handled have to
+    /// finish once the server is shutting down, 30 seconds by
+    /// default. The connections still open after it are dropped.
+    /// See
+    /// [`listen_with_shutdown()`](../struct.Octane.html#method.listen_with_shutdown)
+    pub shutdown: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Returns a new Timeouts struct instance with the default
+    /// timeouts
+    pub fn new() -> Self {
+        Timeouts {
+            header_read: Some(Duration::from_secs(30)),
+            body_read: Some(Duration::from_secs(60)),
+            handler: None,
+            write: None,
+            shutdown: Some(Duration::from_secs(30)),
+        }
+    }
+    /// Sets the time the client has to send the request line and
+    /// the headers
+    pub fn header_read(&mut self, duration: Duration) -> &mut Self {
+        self.header_read = Some(duration);
+        self
+    }
+    /// Sets the time the client has to send the body
+    pub fn body_read(&mut self, duration: Duration) -> &mut Self {
+        self.body_read = Some(duration);
+        self
+    }
+    /// Sets the time the closures have to handle the request
+    pub fn handler(&mut self, duration: Duration) -> &mut Self {
+        self.handler = Some(duration);
+        self
+    }
+    /// Sets the time sending the response can take
+    pub fn write(&mut self, duration: Duration) -> &mut Self {
+        self.write = Some(duration);
+        self
+    }
+    /// Sets the time the requests which are being handled have
+    /// to finish once the server is shutting down
+    pub fn shutdown(&mut self, duration: Duration) -> &mut Self {
+        self.shutdown = Some(duration);
+        self
+    }
+}
+
+/// SocketOptions struct holds the options the listening sockets
+/// are created with.
+///
+/// ```no_run
+/// use octane::Octane;
+/// use octane::config::Config;
+///
+/// let mut app = Octane::new();
+/// app.socket()
+///     .backlog(1024)
+///     .reuse_port(true);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// The most connections which can wait to be accepted, 2048
+    /// by default
+    pub backlog: i32,
+    /// Sets `SO_REUSEPORT` so several processes can listen on
+    /// the same port and share the connections, false by default.
+    /// It is only supported on unix.
+    pub reuse_port: bool,
+    /// Makes the sockets bound to IPv6 addresses only accept IPv6
+    /// connections, false by default so binding to `[::]` accepts
+    /// IPv4 connections too
+    pub only_v6: bool,
+    /// The permissions the socket file of
+    /// [`listen_unix()`](../struct.Octane.html#method.listen_unix)
+    /// is given, like `0o660` to let a proxy in the same group
+    /// connect. `None` by default which leaves them to the umask
+    pub unix_mode: Option<u32>,
+}
+
+impl SocketOptions {
+    /// Returns a new SocketOptions struct instance with the
+    /// default options
+    pub fn new() -> Self {
+        SocketOptions {
+            backlog: 2048,
+            reuse_port: false,
+            only_v6: false,
+            unix_mode: None,
+        }
+    }
+    /// Sets the most connections which can wait to be accepted
+    pub fn backlog(&mut self, backlog: i32) -> &mut Self {
+        self.backlog = backlog;
+        self
+    }
+    /// Sets if `SO_REUSEPORT` is set on the sockets
+    pub fn reuse_port(&mut self, reuse_port: bool) -> &mut Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+    /// Sets if the sockets bound to IPv6 addresses only accept
+    /// IPv6 connections
+    pub fn only_v6(&mut self, only_v6: bool) -> &mut Self {
+        self.only_v6 = only_v6;
+        self
+    }
+    /// Sets the permissions of the unix socket file
+    pub fn unix_mode(&mut self, mode: u32) -> &mut Self {
+        self.unix_mode = Some(mode);
+        self
+    }
+}
+
+/// ErrorPage is the body sent in place of the default error
+/// page for a status code, see
+/// [`error_page()`](trait.Config.html#tymethod.error_page)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorPage {
+    /// Html which is sent as it is
+    Html(String),
+    /// Location of a file which is sent with the mime type of
+    /// its extension, the default page is sent if it can't be read
+    File(PathBuf),
+}
+
 /// An independent OctaneConfig struct that can be used
 /// separately from the app structure and then be appended
 /// to it.
@@ -101,11 +325,21 @@ impl Ssl {
 /// item. If no config is specified then defaults are used.
 ///
 pub struct OctaneConfig {
-    /// The duration for keep alive requests. It is 5 seconds by default
+    /// The duration an idle keep alive connection is held open for the
+    /// next request. It is 5 seconds by default, `None` closes the
+    /// connection after every response
     pub keep_alive: Option<Duration>,
     /// An instance of the `Ssl` struct to store the values of key and certificates.
     pub ssl: Ssl,
+    /// The limits on the size of the requests
+    pub limits: Limits,
+    /// The timeouts on reading the requests and sending the responses
+    pub timeouts: Timeouts,
+    /// The options the listening sockets are created with
+    pub socket: SocketOptions,
     worker_threads: Option<usize>,
+    error_pages: HashMap<i32, ErrorPage>,
+    fallback_error_page: Option<ErrorPage>,
 }
 
 /// Shared config trait which allows us to use the config
@@ -192,6 +426,92 @@ pub trait Config {
     ///     .cert("cert.pem");
     /// ```
     fn ssl(&mut self, port: u16) -> &mut Ssl;
+    /// Sets the page which is sent for the status code when
+    /// octane answers with it on its own, like the 404 for paths
+    /// which no closure handled or the 400 for malformed requests
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::{Config, ErrorPage, OctaneConfig};
+    /// use octane::responder::StatusCode;
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// config.error_page(
+    ///     StatusCode::NotFound,
+    ///     ErrorPage::File("templates/error.html".into()),
+    /// );
+    /// ```
+    ///
+    /// Or with Octane struct
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    /// use octane::config::{Config, ErrorPage};
+    /// use octane::responder::StatusCode;
+    ///
+    /// let mut app = Octane::new();
+    /// app.error_page(
+    ///     StatusCode::BadRequest,
+    ///     ErrorPage::Html("<h1>Bad request</h1>".to_owned()),
+    /// );
+    /// ```
+    fn error_page(&mut self, status: StatusCode, page: ErrorPage);
+    /// Sets the page which is sent for the error status codes
+    /// that don't have a page of their own
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::{Config, ErrorPage, OctaneConfig};
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// config.fallback_error_page(ErrorPage::Html("<h1>Something went wrong</h1>".to_owned()));
+    /// ```
+    fn fallback_error_page(&mut self, page: ErrorPage);
+    /// Returns the limits on the size of the requests so they
+    /// can be changed
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::{Config, OctaneConfig};
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// config
+    ///     .limits()
+    ///     .header_bytes(8 * 1024)
+    ///     .uri_length(2048);
+    /// ```
+    fn limits(&mut self) -> &mut Limits;
+    /// Returns the timeouts on reading the requests and sending
+    /// the responses so they can be changed
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::{Config, OctaneConfig};
+    /// use std::time::Duration;
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// config
+    ///     .timeouts()
+    ///     .body_read(Duration::from_secs(20))
+    ///     .write(Duration::from_secs(60));
+    /// ```
+    fn timeouts(&mut self) -> &mut Timeouts;
+    /// Returns the options the listening sockets are created
+    /// with so they can be changed
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::{Config, OctaneConfig};
+    ///
+    /// let mut config = OctaneConfig::new();
+    /// config.socket().only_v6(true);
+    /// ```
+    fn socket(&mut self) -> &mut SocketOptions;
 }
 /// Octane config which can be used independently to
 /// configure the server settings.
@@ -204,14 +524,33 @@ impl OctaneConfig {
     pub fn new() -> Self {
         OctaneConfig {
             ssl: Ssl::new(),
+            limits: Limits::new(),
+            timeouts: Timeouts::new(),
+            socket: SocketOptions::new(),
             keep_alive: Some(Duration::from_secs(5)),
             worker_threads: None,
+            error_pages: HashMap::new(),
+            fallback_error_page: None,
         }
     }
     // Appends a settings instance to self
     pub(crate) fn append(&mut self, settings: Self) {
         self.ssl = settings.ssl;
         self.keep_alive = settings.keep_alive;
+        self.limits = settings.limits;
+        self.timeouts = settings.timeouts;
+        self.socket = settings.socket;
+        self.error_pages.extend(settings.error_pages);
+        if settings.fallback_error_page.is_some() {
+            self.fallback_error_page = settings.fallback_error_page;
+        }
+    }
+    // The page set for the status code, or the fallback page
+    pub(crate) fn get_error_page(&self, status: StatusCode) -> Option<&ErrorPage> {
+        let code: i32 = status.into();
+        self.error_pages
+            .get(&code)
+            .or_else(|| self.fallback_error_page.as_ref())
     }
 
     /// Sets the number of worker threads, this is settings
@@ -243,6 +582,9 @@ impl OctaneConfig {
 
 default!(OctaneConfig);
 default!(Ssl);
+default!(Limits);
+default!(Timeouts);
+default!(SocketOptions);
 
 impl Config for OctaneConfig {
     fn set_keepalive(&mut self, duration: Duration) {
@@ -257,4 +599,19 @@ impl Config for OctaneConfig {
         self.ssl.port = port;
         &mut self.ssl
     }
+    fn error_page(&mut self, status: StatusCode, page: ErrorPage) {
+        self.error_pages.insert(status.into(), page);
+    }
+    fn fallback_error_page(&mut self, page: ErrorPage) {
+        self.fallback_error_page = Some(page);
+    }
+    fn limits(&mut self) -> &mut Limits {
+        &mut self.limits
+    }
+    fn timeouts(&mut self) -> &mut Timeouts {
+        &mut self.timeouts
+    }
+    fn socket(&mut self) -> &mut SocketOptions {
+        &mut self.socket
+    }
 }
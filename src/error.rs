@@ -1,18 +1,21 @@
-use crate::constants::NOT_FOUND;
+use crate::config::{ErrorPage, OctaneConfig};
 use crate::responder::Response;
 use crate::responder::StatusCode;
+use crate::util::until;
 use crate::Octane;
 use std::error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::marker::Unpin;
 use tokio::io::AsyncWrite;
+use tokio::time::Instant;
 
 /// The Error structure holds the kind of error code and
-/// the location of the custom 404 file, if given by the user
-/// and manages sending errors on internal http errors and other instances
-/// will send with blank content if no 404 file is found or will send the
-/// 404 file directly if given.
+/// manages sending errors on internal http errors and other
+/// instances. It sends the page set for the status code in the
+/// config if there is one, see
+/// [`error_page()`](../config/trait.Config.html#tymethod.error_page),
+/// or a default page showing the status code otherwise.
 ///
 /// You will not have to use this manually, to send errors on your own, you
 /// can do so by just specifying the error code and the content like
@@ -24,38 +27,105 @@ pub struct Error {
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// Custom error type for invalid paths
 pub struct InvalidPathError;
+/// The error returned by
+/// [`req.param()`](../request/struct.MatchedRequest.html#method.param)
 #[derive(Debug, Clone, PartialEq, Eq)]
-// Custom error type for invalid SSL certificates
-pub struct InvalidCertError;
+pub enum ParamError<E> {
+    /// The path has no url variable with the given name
+    Missing,
+    /// The value of the url variable couldn't be parsed
+    Invalid(E),
+}
+/// The error returned by
+/// [`app.url_for()`](../struct.Octane.html#method.url_for)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+    /// No route was added with the given name
+    UnknownRoute(String),
+    /// No value was given for the url variable
+    MissingParam(String),
+    /// The value given for the url variable doesn't fit its constraint
+    InvalidParam(String),
+}
 
 /// Takes in a http stream and a error code and sends to the client
 #[macro_export]
 #[doc(hidden)]
 macro_rules! declare_error {
-    ($stream : expr, $error_type : expr) => {
-        Error::err($error_type, $stream).await?;
+    ($stream : expr, $error_type : expr, $settings : expr) => {
+        Error::err($error_type, $stream, $settings).await?;
         return Ok(());
     };
 }
 
 impl Error {
-    pub async fn err<S>(status_code: StatusCode, stream: S) -> Result<(), Box<dyn error::Error>>
+    pub async fn err<S>(
+        status_code: StatusCode,
+        stream: S,
+        settings: &OctaneConfig,
+    ) -> Result<(), Box<dyn error::Error>>
     where
         S: AsyncWrite + Unpin,
     {
-        Error { kind: status_code }.send(stream).await
+        Error { kind: status_code }.send(stream, settings).await
+    }
+    // Tells if the status code is a client or a server error
+    pub(crate) fn is_error(status_code: StatusCode) -> bool {
+        let code: i32 = status_code.into();
+        code >= 400
+    }
+    // Fills the response with the page set in the config for the
+    // status code, the default page is used when there's none
+    pub(crate) fn render_page(
+        status_code: StatusCode,
+        res: &mut Response,
+        settings: &OctaneConfig,
+    ) {
+        match settings.get_error_page(status_code) {
+            Some(ErrorPage::Html(page)) => {
+                res.status(status_code)
+                    .set("Content-Type", "text/html")
+                    .send(page);
+            }
+            Some(ErrorPage::File(path)) => {
+                res.status(status_code);
+                if res.send_file(&path.to_string_lossy()).is_err() {
+                    Self::render(status_code, res);
+                }
+            }
+            None => Self::render(status_code, res),
+        }
+    }
+    // Fills the response with the error page for the given status code,
+    // overwriting any body that was already there
+    fn render(status_code: StatusCode, res: &mut Response) {
+        res.status(status_code)
+            .set("Content-Type", "text/html")
+            .send(Self::page(status_code));
     }
-    async fn send<S>(self, stream: S) -> Result<(), Box<dyn error::Error>>
+    // The default error page, showing the status code and its reason
+    fn page(status_code: StatusCode) -> String {
+        let code: i32 = status_code.into();
+        let status = format!("{} {}", code, status_code.to_string().to_uppercase());
+        format!(
+            r#"<!DOCTYPE html><html><head><title>{0} - OCTANE</title></head><body style="padding: 20px;"><h2 style="text-align: center;">{0}</h2><hr><h5>OCTANE - 0.1</h2></body></html>"#,
+            status
+        )
+    }
+    async fn send<S>(self, stream: S, settings: &OctaneConfig) -> Result<(), Box<dyn error::Error>>
     where
         S: AsyncWrite + Unpin,
     {
-        let mut res = Response::new_from_slice(NOT_FOUND.as_bytes());
-        res.status(self.kind)
-            .default_headers()
-            .set("Content-Type", "text/html");
+        let mut res = Response::new_empty();
+        Self::render_page(self.kind, &mut res, settings);
+        res.set("Connection", "close");
 
         let response = res.get_data();
-        Octane::send(response, stream).await?;
+        let deadline = settings.timeouts.write.map(|v| Instant::now() + v);
+        // the connection is closed after errors anyway
+        if let Some(sent) = until(deadline, Octane::send(response, stream)).await {
+            sent?;
+        }
         Ok(())
     }
 }
@@ -68,3 +138,67 @@ impl Display for InvalidPathError {
 }
 
 impl error::Error for InvalidPathError {}
+
+impl<E: Display> Display for ParamError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ParamError::Missing => write!(f, "No url variable with the given name"),
+            ParamError::Invalid(e) => write!(f, "Invalid url variable: {}", e),
+        }
+    }
+}
+
+impl<E: fmt::Debug + Display> error::Error for ParamError<E> {}
+
+impl Display for UrlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            UrlError::UnknownRoute(name) => write!(f, "No route named {}", name),
+            UrlError::MissingParam(name) => write!(f, "Missing url variable {}", name),
+            UrlError::InvalidParam(name) => write!(f, "Invalid value for url variable {}", name),
+        }
+    }
+}
+
+impl error::Error for UrlError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use tokio::io::AsyncReadExt;
+
+    async fn render(status_code: StatusCode, settings: &OctaneConfig) -> (String, String) {
+        let mut res = Response::new_empty();
+        Error::render_page(status_code, &mut res, settings);
+        let (head, mut body) = res.get_data();
+        let mut page = String::new();
+        body.read_to_string(&mut page).await.unwrap();
+        (head, page)
+    }
+
+    #[crate::test]
+    async fn success_error_pages() {
+        // The page of the status should be used before the fallback.
+        let mut settings = OctaneConfig::new();
+        let (head, page) = render(StatusCode::BadRequest, &settings).await;
+        assert!(head.starts_with("HTTP/1.1 400"));
+        assert!(page.contains("400 BAD REQUEST"));
+        settings.error_page(
+            StatusCode::BadRequest,
+            ErrorPage::Html("<h1>Bad</h1>".to_owned()),
+        );
+        settings.fallback_error_page(ErrorPage::File("templates/error.html".into()));
+        let (head, page) = render(StatusCode::BadRequest, &settings).await;
+        assert!(head.starts_with("HTTP/1.1 400"));
+        assert_eq!(page, "<h1>Bad</h1>");
+        let (head, page) = render(StatusCode::NotFound, &settings).await;
+        assert!(head.starts_with("HTTP/1.1 404"));
+        assert!(head.contains("text/html"));
+        assert!(page.contains("<title>404 NOT FOUND - OCTANE</title>"));
+        // files which can't be read fall back to the default page
+        settings.error_page(StatusCode::NotFound, ErrorPage::File("missing.html".into()));
+        let (_, page) = render(StatusCode::NotFound, &settings).await;
+        assert!(page.contains("<h2 style=\"text-align: center;\">404 NOT FOUND</h2>"));
+    }
+}
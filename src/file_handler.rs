@@ -10,7 +10,6 @@ use std::path::PathBuf;
 /// to manage files, contents and extensions also
 /// to decide their mime types accordingly
 pub struct FileHandler {
-    pub file_name: String,
     pub file: AsyncReader<File>,
     pub extension: String,
     pub meta: Metadata,
@@ -40,7 +39,6 @@ impl FileHandler {
                     .and_then(OsStr::to_str)
                     .unwrap_or("");
                 Ok(FileHandler {
-                    file_name: path.file_name().and_then(OsStr::to_str).unwrap().to_owned(),
                     file: AsyncReader::new(file),
                     extension: extension.to_owned(),
                     meta,
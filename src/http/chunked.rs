@@ -0,0 +1,282 @@
+use crate::constants::{BUF_SIZE, B_CRLF};
+use crate::util::find_in_slice;
+use std::pin::Pin;
+use std::str;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf, Result};
+
+/// The state the decoder is in after going through the
+/// data it has been given
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeState {
+    /// The whole body has been decoded, holds the number of
+    /// bytes the encoded body took up including the trailers
+    Done(usize),
+    /// More data is required to finish the body
+    Partial,
+    /// The data isn't valid chunked encoding
+    Malformed,
+    /// The body or a line of its framing is over the limits
+    /// of the decoder
+    TooLarge,
+}
+
+// Decodes a chunked transfer encoded request body, the decoder
+// remembers how far it got so it can be called again with the
+// same (but longer) data when more of it arrives
+pub struct ChunkedDecoder {
+    pos: usize,
+    // the most bytes the decoded body can take up, and the most
+    // a size line or the trailers can take up
+    body_limit: usize,
+    line_limit: usize,
+    pub body: Vec<u8>,
+    pub trailers: String,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        Self::with_limits(usize::MAX, usize::MAX)
+    }
+
+    pub fn with_limits(body_limit: usize, line_limit: usize) -> Self {
+        ChunkedDecoder {
+            pos: 0,
+            body_limit,
+            line_limit,
+            body: Vec::new(),
+            trailers: String::new(),
+        }
+    }
+
+    // data should always start from the beginning of the body
+    pub fn decode(&mut self, data: &[u8]) -> DecodeState {
+        loop {
+            let rest = &data[self.pos..];
+            let line_end = match find_in_slice(rest, B_CRLF) {
+                Some(v) => v,
+                None if rest.len() > self.line_limit => return DecodeState::TooLarge,
+                None => return DecodeState::Partial,
+            };
+            // chunk extensions come after a ';' and are ignored
+            let size = match str::from_utf8(&rest[..line_end])
+                .ok()
+                .and_then(|line| line.split(';').next())
+                .map(str::trim)
+                // from_str_radix would take a sign too
+                .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|size| usize::from_str_radix(size, 16).ok())
+            {
+                Some(v) => v,
+                None => return DecodeState::Malformed,
+            };
+            // checked before the chunk arrives so that it's never buffered
+            if self.body.len().saturating_add(size) > self.body_limit {
+                return DecodeState::TooLarge;
+            }
+            let chunk = &rest[line_end + 2..];
+            if size == 0 {
+                return self.decode_trailers(chunk, data.len() - chunk.len());
+            }
+            if chunk.len() < size.saturating_add(2) {
+                return DecodeState::Partial;
+            }
+            if &chunk[size..size + 2] != B_CRLF {
+                return DecodeState::Malformed;
+            }
+            self.body.extend_from_slice(&chunk[..size]);
+            self.pos += line_end + 2 + size + 2;
+        }
+    }
+
+    // The last chunk is followed by optional trailer headers and
+    // a blank line, offset is where they start in the body
+    fn decode_trailers(&mut self, rest: &[u8], offset: usize) -> DecodeState {
+        if rest.starts_with(B_CRLF) {
+            return DecodeState::Done(offset + 2);
+        }
+        match find_in_slice(rest, b"\r\n\r\n") {
+            Some(end) if end > self.line_limit => DecodeState::TooLarge,
+            Some(end) => match str::from_utf8(&rest[..end]) {
+                Ok(trailers) => {
+                    self.trailers = trailers.to_owned();
+                    DecodeState::Done(offset + end + 4)
+                }
+                Err(_) => DecodeState::Malformed,
+            },
+            None if rest.len() > self.line_limit => DecodeState::TooLarge,
+            None => DecodeState::Partial,
+        }
+    }
+}
+
+// Wraps a reader of unknown length and frames everything read
+// from it as chunks, ending with the last (empty) chunk
+pub struct ChunkedEncoder<R: AsyncRead + Unpin> {
+    reader: R,
+    pending: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> ChunkedEncoder<R> {
+    pub fn new(reader: R) -> Self {
+        ChunkedEncoder {
+            reader,
+            pending: Vec::new(),
+            pos: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ChunkedEncoder<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        loop {
+            if self.pos < self.pending.len() {
+                let len = buf.remaining().min(self.pending.len() - self.pos);
+                buf.put_slice(&self.pending[self.pos..self.pos + len]);
+                self.pos += len;
+                return Poll::Ready(Ok(()));
+            }
+            if self.finished {
+                return Poll::Ready(Ok(()));
+            }
+            let mut chunk = [0; BUF_SIZE];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            match Pin::new(&mut self.reader).poll_read(cx, &mut chunk_buf) {
+                Poll::Ready(Ok(())) => (),
+                other => return other,
+            }
+            let read = chunk_buf.filled();
+            let mut pending = format!("{:X}\r\n", read.len()).into_bytes();
+            pending.extend_from_slice(read);
+            pending.extend_from_slice(B_CRLF);
+            if read.is_empty() {
+                self.finished = true;
+            }
+            self.pending = pending;
+            self.pos = 0;
+        }
+    }
+}
+
+// Checks if chunked is the final encoding in a Transfer-Encoding
+// header value
+pub fn is_chunked(transfer_encoding: &str) -> bool {
+    transfer_encoding
+        .rsplit(',')
+        .next()
+        .map_or(false, |v| v.trim().eq_ignore_ascii_case("chunked"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn success_standard() {
+        // Decoding should work as expected.
+        let data = b"4\r\nWiki\r\n5;name=value\r\npedia\r\n0\r\n\r\nGET";
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(decoder.decode(data), DecodeState::Done(data.len() - 3));
+        assert_eq!(decoder.body, b"Wikipedia");
+        assert!(decoder.trailers.is_empty());
+    }
+
+    #[test]
+    fn success_trailers() {
+        // Trailers should be kept aside for the headers.
+        let data = b"3\r\nabc\r\n0\r\nExpires: never\r\nX-Foo: bar\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(decoder.decode(data), DecodeState::Done(data.len()));
+        assert_eq!(decoder.body, b"abc");
+        assert_eq!(decoder.trailers, "Expires: never\r\nX-Foo: bar");
+    }
+
+    #[test]
+    fn success_partial() {
+        // Decoding should resume when more data arrives.
+        let data = b"A\r\n0123456789\r\n1\r\nx\r\n0\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        for end in 0..data.len() {
+            assert_eq!(decoder.decode(&data[..end]), DecodeState::Partial);
+        }
+        assert_eq!(decoder.decode(data), DecodeState::Done(data.len()));
+        assert_eq!(decoder.body, b"0123456789x");
+    }
+
+    #[test]
+    fn fail_malformed() {
+        // Bad sizes and missing delimiters should error.
+        assert_eq!(
+            ChunkedDecoder::new().decode(b"zz\r\nabc\r\n0\r\n\r\n"),
+            DecodeState::Malformed
+        );
+        assert_eq!(
+            ChunkedDecoder::new().decode(b"2\r\nabc\r\n0\r\n\r\n"),
+            DecodeState::Malformed
+        );
+        assert_eq!(
+            ChunkedDecoder::new().decode(b"\r\nabc\r\n0\r\n\r\n"),
+            DecodeState::Malformed
+        );
+        assert_eq!(
+            ChunkedDecoder::new().decode(b"+3\r\nabc\r\n0\r\n\r\n"),
+            DecodeState::Malformed
+        );
+    }
+
+    #[test]
+    fn fail_too_large() {
+        // The decoded size should be limited, not the encoded one.
+        let data = b"1\r\na\r\n1\r\nb\r\n1\r\nc\r\n0\r\n\r\n";
+        let mut decoder = ChunkedDecoder::with_limits(3, 16);
+        assert_eq!(decoder.decode(data), DecodeState::Done(data.len()));
+        assert_eq!(decoder.body, b"abc");
+        // A chunk going over the limit should error before it arrives.
+        assert_eq!(
+            ChunkedDecoder::with_limits(3, 16).decode(b"2\r\nab\r\n2\r\n"),
+            DecodeState::TooLarge
+        );
+        // So should size lines and trailers which don't end.
+        assert_eq!(
+            ChunkedDecoder::with_limits(3, 16).decode(&[b'1'; 17]),
+            DecodeState::TooLarge
+        );
+        assert_eq!(
+            ChunkedDecoder::with_limits(3, 16).decode(b"0\r\nX-Trailer: 0123456789"),
+            DecodeState::TooLarge
+        );
+    }
+
+    #[crate::test]
+    async fn success_encoder() {
+        // Encoding should frame the data and end with the last chunk.
+        use tokio::io::AsyncReadExt;
+
+        let mut encoded = String::new();
+        ChunkedEncoder::new(std::io::Cursor::new(b"Wikipedia".to_vec()))
+            .read_to_string(&mut encoded)
+            .await
+            .unwrap();
+        assert_eq!(encoded, "9\r\nWikipedia\r\n0\r\n\r\n");
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(
+            decoder.decode(encoded.as_bytes()),
+            DecodeState::Done(encoded.len())
+        );
+        assert_eq!(decoder.body, b"Wikipedia");
+    }
+
+    #[test]
+    fn success_is_chunked() {
+        assert!(is_chunked("chunked"));
+        assert!(is_chunked("gzip, Chunked"));
+        assert!(!is_chunked("chunked, gzip"));
+    }
+}
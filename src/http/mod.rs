@@ -5,6 +5,7 @@ use std::time::Duration;
 use http10::*;
 use http11::*;
 
+pub mod chunked;
 pub mod http10;
 pub mod http11;
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,6 +19,7 @@ pub struct Http<'a> {
     pub request: &'a Request<'a>,
     pub err_code: Option<StatusCode>,
     pub keep_alive: KeepAliveState,
+    pub max_requests: Option<u64>,
 }
 
 impl<'a> Http<'a> {
@@ -26,6 +28,7 @@ impl<'a> Http<'a> {
             request,
             err_code: None,
             keep_alive: KeepAliveState::Close,
+            max_requests: None,
         };
         match request.request_line.version {
             HttpVersion::Http11 => http11_check(&mut handler),
@@ -47,4 +50,67 @@ impl<'a> Http<'a> {
     pub fn keep_alive(&self) -> bool {
         !(self.keep_alive == KeepAliveState::Close)
     }
+    // Checks if the comma separated `Connection` header
+    // contains the given token, ignoring case
+    pub fn has_connection_token(&self, token: &str) -> bool {
+        self.request
+            .headers
+            .get("connection")
+            .map(|value| {
+                value
+                    .split(',')
+                    .any(|v| v.trim().eq_ignore_ascii_case(token))
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::{Headers, RequestLine};
+
+    fn validate_with(request_line: &str, headers: &str, exec: impl FnOnce(Http)) {
+        let headers = Headers::parse(headers.to_owned()).unwrap();
+        let request =
+            Request::parse(RequestLine::parse(request_line).unwrap(), &headers, b"").unwrap();
+        exec(Http::validate(&request));
+    }
+
+    #[test]
+    fn success_http11_persistent() {
+        // Http 1.1 connections should stay open by default
+        validate_with("GET / HTTP/1.1", "Host: localhost", |http| {
+            assert_eq!(http.keep_alive, KeepAliveState::UserDefined);
+        });
+        validate_with(
+            "GET / HTTP/1.1",
+            "Host: localhost\r\nConnection: Upgrade, Close",
+            |http| {
+                assert_eq!(http.keep_alive, KeepAliveState::Close);
+            },
+        );
+    }
+
+    #[test]
+    fn success_http10_keepalive() {
+        // Http 1.0 connections should only stay open when asked for
+        validate_with("GET / HTTP/1.0", "Host: localhost", |http| {
+            assert!(!http.keep_alive());
+        });
+        validate_with("GET / HTTP/1.0", "Connection: keep-alive", |http| {
+            assert_eq!(http.keep_alive, KeepAliveState::UserDefined);
+        });
+        validate_with(
+            "GET / HTTP/1.0",
+            "Connection: Keep-Alive\r\nKeep-Alive: timeout=3, max=10",
+            |http| {
+                assert_eq!(
+                    http.keep_alive,
+                    KeepAliveState::Particular(Duration::from_secs(3))
+                );
+                assert_eq!(http.max_requests, Some(10));
+            },
+        );
+    }
 }
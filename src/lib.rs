@@ -89,6 +89,11 @@ pub(crate) mod server;
 /// Server struct that manages request/response and allows the routes to enter in
 pub use crate::server::Octane;
 pub(crate) mod server_builder;
+pub use crate::server_builder::ToListenAddrs;
+/// The state module holds the application state and per request extensions
+pub mod state;
+/// The testing module has a client which runs requests through the app without a network
+pub mod testing;
 pub(crate) mod time;
 pub(crate) mod tls;
 pub(crate) mod util;
@@ -105,7 +110,8 @@ pub use octane_macros::test;
 /// [`Config`](config/trait.Config.html) trait, [`Octane`](struct.Octane.html) main server
 /// and [`Router`](router/struct.Router.html) struct with the [`Flow`](router/enum.Flow.html)
 /// and the [`Value`](../octane_json/enum.Value.html) enum, the [`route`](macro.route.html),
-/// [`route_next`](macro.route_next.html), [`path`](macro.path.html), [`route_stop`](macro.route_stop.html)
+/// [`route_async`](macro.route_async.html), [`route_try`](macro.route_try.html), [`route_try_async`](macro.route_try_async.html),
+/// [`route_error`](macro.route_error.html), [`route_next`](macro.route_next.html), [`path`](macro.path.html), [`route_stop`](macro.route_stop.html)
 /// macros with the [`ToJSON`](../octane_json/convert/trait.ToJSON.html)
 /// /[`FromJSON`](../octane_json/convert/trait.FromJSON.html) derive macros
 pub mod prelude {
@@ -113,7 +119,7 @@ pub mod prelude {
     pub use crate::json::{FromJSON, ToJSON};
     pub use crate::Octane;
     pub use crate::{
-        route, route_next, route_stop,
+        route, route_async, route_error, route_next, route_stop, route_try, route_try_async,
         router::{Flow, Route, Router},
     };
     pub use octane_json::Value;
@@ -1,6 +1,7 @@
-use crate::router::Closure;
+use crate::router::Handler;
 
 pub struct Closures {
-    pub closure: Closure,
+    pub closure: Handler,
     pub index: usize,
+    pub name: Option<String>,
 }
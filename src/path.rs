@@ -1,17 +1,39 @@
 use crate::error::InvalidPathError;
 use crate::{default, deref};
+use regex::Regex;
 use std::collections::{hash_map, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::iter::{FromIterator, Iterator, Map};
 use std::path::PathBuf as StdPathBuf;
 use std::str::FromStr;
 
 // Custom PathBuf for managing custom url queries and variables,
 // Provides methods to match and check on urls
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub struct PathBuf {
     pub chunks: Vec<String>,
+    // the chunks joined with slashes, wildcards borrow from it
+    joined: String,
+    // the constraints of the url variables of a route, compiled
+    // once when it's parsed, empty for the paths of requests
+    constraints: Vec<Option<Constraint>>,
+}
+
+// paths are the same if their chunks are
+impl PartialEq for PathBuf {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunks == other.chunks
+    }
+}
+
+impl Eq for PathBuf {}
+
+impl Hash for PathBuf {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.chunks.hash(state);
+    }
 }
 
 impl fmt::Display for PathBuf {
@@ -26,7 +48,25 @@ impl fmt::Display for PathBuf {
 
 impl PathBuf {
     pub fn new() -> Self {
-        PathBuf { chunks: Vec::new() }
+        Self::from_chunks(Vec::new())
+    }
+
+    fn from_chunks(chunks: Vec<String>) -> Self {
+        Self::from_parts(chunks, Vec::new())
+    }
+
+    fn from_parts(chunks: Vec<String>, constraints: Vec<Option<Constraint>>) -> Self {
+        PathBuf {
+            joined: chunks.join("/"),
+            chunks,
+            constraints,
+        }
+    }
+
+    // The chunks starting from the given one, joined with slashes
+    pub fn rest(&self, from: usize) -> &str {
+        let start: usize = self.chunks[..from].iter().map(|v| v.len() + 1).sum();
+        &self.joined[start.min(self.joined.len())..]
     }
 
     pub fn chunks(&self) -> &Vec<String> {
@@ -55,7 +95,34 @@ impl PathBuf {
             }
             chunks.push(chunk.to_owned());
         }
-        Ok(PathBuf { chunks })
+        Ok(Self::from_chunks(chunks))
+    }
+
+    // Parse the path of a route, the constraints on the url
+    // variables should be valid
+    pub fn parse_route(path: &str) -> Result<Self, InvalidPathError> {
+        let mut path = Self::parse(path)?;
+        let mut constraints = Vec::with_capacity(path.len());
+        for (n, chunk) in path.iter().enumerate() {
+            // wildcards take the rest of the path, so they come last
+            if chunk.starts_with('*') && n + 1 != path.len() {
+                return Err(InvalidPathError);
+            }
+            let spec = if chunk.starts_with(':') {
+                split_var(chunk).1
+            } else {
+                None
+            };
+            constraints.push(spec.map(Constraint::parse).transpose()?);
+        }
+        path.constraints = constraints;
+        Ok(path)
+    }
+
+    // The constraint on the url variable of the chunk at the
+    // given index, if the path is a route and it has one
+    pub fn constraint(&self, n: usize) -> Option<&Constraint> {
+        self.constraints.get(n)?.as_ref()
     }
 
     pub fn check_starts_with(&self, other: &PathBuf) -> bool {
@@ -72,13 +139,17 @@ impl PathBuf {
         &'a self,
         other: &'b PathBuf,
     ) -> Option<HashMap<&'a str, &'b str>> {
-        if self.len() != other.len() {
+        // wildcards take at least one chunk
+        let wildcard = self.has_wildcard();
+        if self.len() > other.len() || (!wildcard && self.len() != other.len()) {
             return None;
         }
         let mut vars = HashMap::new();
-        for (a, b) in self.iter().zip(other.iter()) {
-            if a.as_bytes()[0] == b':' {
-                vars.insert(&a[1..], &b[..]);
+        for (n, (a, b)) in self.iter().zip(other.iter()).enumerate() {
+            if a.as_bytes()[0] == b'*' {
+                vars.insert(&a[1..], other.rest(n));
+            } else if a.as_bytes()[0] == b':' {
+                vars.insert(split_var(a).0, &b[..]);
             } else if a != b {
                 return None;
             }
@@ -94,6 +165,40 @@ impl PathBuf {
         Some(())
     }
 
+    /// Check if the given pathbuf starts with a Pathbuf, the url
+    /// variables are only taken from the prefix
+    #[cfg(feature = "url_variables")]
+    pub fn check_matches_prefix<'a, 'b>(
+        &'a self,
+        other: &'b PathBuf,
+    ) -> Option<HashMap<&'a str, &'b str>> {
+        if self.len() > other.len() {
+            return None;
+        }
+        let mut vars = HashMap::new();
+        for (n, (a, b)) in self.iter().zip(other.iter()).enumerate() {
+            if a.as_bytes()[0] == b':' {
+                if let Some(constraint) = self.constraint(n) {
+                    if !constraint.matches(b) {
+                        return None;
+                    }
+                }
+                vars.insert(split_var(a).0, &b[..]);
+            } else if a != b {
+                return None;
+            }
+        }
+        Some(vars)
+    }
+
+    #[cfg(not(feature = "url_variables"))]
+    pub fn check_matches_prefix(&self, other: &PathBuf) -> Option<()> {
+        if self.len() > other.len() || self.iter().ne(other.iter().take(self.len())) {
+            return None;
+        }
+        Some(())
+    }
+
     pub fn subtract(&self, other: &PathBuf) -> Option<PathBuf> {
         if self.len() < other.len() {
             return None;
@@ -101,30 +206,61 @@ impl PathBuf {
         if self.iter().take(other.len()).ne(other.iter()) {
             return None;
         }
-        Some(PathBuf {
-            chunks: self.chunks[other.len()..].to_vec(),
-        })
+        let constraints = self.constraints.get(other.len()..).unwrap_or_default();
+        Some(Self::from_parts(
+            self.chunks[other.len()..].to_vec(),
+            constraints.to_vec(),
+        ))
     }
 
     pub fn concat_owned(&self, other: PathBuf) -> PathBuf {
-        PathBuf {
-            chunks: self
-                .chunks
+        let constraints = self
+            .constraints_padded()
+            .chain(other.constraints_padded())
+            .collect();
+        Self::from_parts(
+            self.chunks
                 .iter()
                 .cloned()
                 .chain(other.chunks.into_iter())
                 .collect(),
-        }
+            constraints,
+        )
     }
     pub fn concat(&self, other: &PathBuf) -> PathBuf {
-        PathBuf {
-            chunks: self
-                .chunks
+        Self::from_parts(
+            self.chunks
                 .iter()
                 .cloned()
                 .chain(other.chunks.iter().cloned())
                 .collect(),
-        }
+            self.constraints_padded()
+                .chain(other.constraints_padded())
+                .collect(),
+        )
+    }
+    // The constraints of every chunk, so that the ones of two
+    // paths still line up with their chunks once joined
+    fn constraints_padded(&self) -> impl Iterator<Item = Option<Constraint>> + '_ {
+        (0..self.len()).map(move |n| self.constraint(n).cloned())
+    }
+    // Ranks every chunk of a route, static chunks come before url
+    // variables which come before wildcards. Comparing the ranks
+    // of two routes tells which one is more specific
+    pub fn specificity(&self) -> impl Iterator<Item = u8> + '_ {
+        self.iter().map(|chunk| match chunk.as_bytes()[0] {
+            b':' => 1,
+            b'*' => 2,
+            _ => 0,
+        })
+    }
+    // The path as it's written in a route, with a leading slash
+    pub fn pattern(&self) -> String {
+        format!("/{}", self.joined)
+    }
+    // Tells if the last chunk is a wildcard
+    pub fn has_wildcard(&self) -> bool {
+        self.last().map_or(false, |v| v.starts_with('*'))
     }
 }
 
@@ -150,15 +286,93 @@ pub struct PathData<T> {
 
 #[derive(Debug, Clone)]
 pub struct MatchedPath<'a, T> {
+    pub orig_path: &'a PathBuf,
     #[cfg(feature = "url_variables")]
     pub vars: HashMap<&'a str, &'a str>,
     pub data: &'a T,
 }
 
+// A constraint on the value of a url variable, written after
+// the name as a type like `:id<u64>` or as a regex like
+// `:slug([a-z-]+)`
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    spec: String,
+    kind: ConstraintKind,
+}
+
+#[derive(Debug, Clone)]
+enum ConstraintKind {
+    Type(fn(&str) -> bool),
+    Pattern(Regex),
+}
+
+impl Constraint {
+    fn parse(spec: &str) -> Result<Self, InvalidPathError> {
+        // the spec is the inner part along with its brackets
+        if spec.len() < 2 {
+            return Err(InvalidPathError);
+        }
+        let inner = &spec[1..spec.len() - 1];
+        let kind = if spec.starts_with('<') {
+            ConstraintKind::Type(match inner {
+                "u8" => parses::<u8>,
+                "u16" => parses::<u16>,
+                "u32" => parses::<u32>,
+                "u64" => parses::<u64>,
+                "u128" => parses::<u128>,
+                "usize" => parses::<usize>,
+                "i8" => parses::<i8>,
+                "i16" => parses::<i16>,
+                "i32" => parses::<i32>,
+                "i64" => parses::<i64>,
+                "i128" => parses::<i128>,
+                "isize" => parses::<isize>,
+                "f32" => parses::<f32>,
+                "f64" => parses::<f64>,
+                "bool" => parses::<bool>,
+                _ => return Err(InvalidPathError),
+            })
+        } else {
+            // the pattern has to match the whole chunk
+            let regex = Regex::new(&format!("^(?:{})$", inner)).map_err(|_| InvalidPathError)?;
+            ConstraintKind::Pattern(regex)
+        };
+        Ok(Constraint {
+            spec: spec.to_owned(),
+            kind,
+        })
+    }
+
+    pub fn matches(&self, chunk: &str) -> bool {
+        match &self.kind {
+            ConstraintKind::Type(check) => check(chunk),
+            ConstraintKind::Pattern(regex) => regex.is_match(chunk),
+        }
+    }
+}
+
+// constraints are the same if they were written the same
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.spec == other.spec
+    }
+}
+
+impl Eq for Constraint {}
+
+impl Hash for Constraint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.spec.hash(state);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PathChunk {
     Chunk(String),
     CatchAll,
+    Constrained(Constraint),
+    Wildcard,
     End,
 }
 
@@ -207,18 +421,25 @@ impl<T> PathNode<T> {
 
     pub fn insert(&mut self, path: PathBuf, data: T) {
         let mut cur = self.unwrap_node_mut();
-        let path_chunks;
-        path_chunks = path.clone().chunks;
-        for chunk in path_chunks.into_iter() {
-            if chunk.as_bytes()[0] == b':' {
+        for (n, chunk) in path.iter().enumerate() {
+            if chunk.as_bytes()[0] == b'*' {
                 cur = cur
-                    .entry(PathChunk::CatchAll)
+                    .entry(PathChunk::Wildcard)
                     .or_default()
                     .unwrap_node_mut();
                 continue;
             }
+            if chunk.as_bytes()[0] == b':' {
+                // the constraints were compiled when parsing the route
+                let key = match path.constraint(n) {
+                    Some(constraint) => PathChunk::Constrained(constraint.clone()),
+                    None => PathChunk::CatchAll,
+                };
+                cur = cur.entry(key).or_default().unwrap_node_mut();
+                continue;
+            }
             cur = cur
-                .entry(PathChunk::Chunk(chunk))
+                .entry(PathChunk::Chunk(chunk.clone()))
                 .or_default()
                 .unwrap_node_mut();
         }
@@ -250,6 +471,17 @@ impl<T> PathNode<T> {
                 let found = v.dfs(&chunks[1..]);
                 ret.extend(found);
             }
+            for (chunk, v) in cur.iter() {
+                if let PathChunk::Constrained(constraint) = chunk {
+                    if constraint.matches(&chunks[0]) {
+                        ret.extend(v.dfs(&chunks[1..]));
+                    }
+                }
+            }
+            // the wildcard matches all of the remaining chunks
+            if let Some(v) = cur.get(&PathChunk::Wildcard) {
+                ret.extend(v.dfs(&[]));
+            }
         }
         ret
     }
@@ -259,6 +491,7 @@ impl<T> PathNode<T> {
         matched
             .into_iter()
             .map(|data| MatchedPath {
+                orig_path: &data.orig_path,
                 #[cfg(feature = "url_variables")]
                 vars: data
                     .orig_path
@@ -327,7 +560,7 @@ impl<'a, T> Iterator for PathNodeIterator<'a, T> {
 }
 
 impl<T> PathNode<T> {
-    pub fn iter(&self) -> PathNodeIterator<T> {
+    pub fn iter(&self) -> PathNodeIterator<'_, T> {
         match self {
             PathNode::Node(n) => PathNodeIterator {
                 stack: vec![n.values()],
@@ -437,6 +670,22 @@ deref!(PathData<T>, T, data);
 deref!(MatchedPath<'a, T>, T, data);
 deref!(PathBuf, Vec<String>, chunks);
 
+// Splits a url variable chunk like `:id<u64>` into its name
+// and the constraint on it, if it has one
+pub fn split_var(chunk: &str) -> (&str, Option<&str>) {
+    let var = &chunk[1..];
+    match var.find(|c| c == '<' || c == '(') {
+        Some(n) if var.ends_with(if &var[n..n + 1] == "<" { '>' } else { ')' }) => {
+            (&var[..n], Some(&var[n..]))
+        }
+        _ => (var, None),
+    }
+}
+
+fn parses<T: FromStr>(chunk: &str) -> bool {
+    chunk.parse::<T>().is_ok()
+}
+
 pub fn is_ctl(c: char) -> bool {
     c < '\x1f' || c == '\x7f'
 }
@@ -486,6 +735,45 @@ mod test {
         assert!(path1.check_matches(&path3).is_none());
     }
 
+    #[test]
+    #[cfg(feature = "url_variables")]
+    fn success_matching_prefix() {
+        // Only the start of the path should have to match.
+        let path1 = PathBuf::parse("/users/:id").unwrap();
+        let path2 = PathBuf::parse("/users/5/posts").unwrap();
+        let path3 = PathBuf::parse("/users").unwrap();
+        let path4 = PathBuf::parse("/posts/5").unwrap();
+        assert_eq!(path1.check_matches_prefix(&path2).unwrap()["id"], "5");
+        assert!(path1.check_matches_prefix(&path3).is_none());
+        assert!(path1.check_matches_prefix(&path4).is_none());
+        assert!(PathBuf::new().check_matches_prefix(&path4).is_some());
+        // Constraints of the prefix should be checked too.
+        let path5 = PathBuf::parse_route("/users/:id<u64>").unwrap();
+        let path6 = PathBuf::parse("/users/me/posts").unwrap();
+        assert_eq!(path5.check_matches_prefix(&path2).unwrap()["id"], "5");
+        assert!(path5.check_matches_prefix(&path6).is_none());
+        let prefixed = PathBuf::parse("/api").unwrap().concat(&path5);
+        assert!(prefixed.check_matches_prefix(&path2).is_none());
+        assert!(prefixed
+            .check_matches_prefix(&PathBuf::parse("/api/users/5").unwrap())
+            .is_some());
+        assert!(prefixed
+            .check_matches_prefix(&PathBuf::parse("/api/users/me").unwrap())
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(not(feature = "url_variables"))]
+    fn success_matching_prefix() {
+        // Only the start of the path should have to match.
+        let path1 = PathBuf::parse("/users").unwrap();
+        let path2 = PathBuf::parse("/users/5").unwrap();
+        let path3 = PathBuf::parse("/posts/5").unwrap();
+        path1.check_matches_prefix(&path2).unwrap();
+        assert!(path1.check_matches_prefix(&path3).is_none());
+        assert!(path2.check_matches_prefix(&path1).is_none());
+    }
+
     #[test]
     fn success_subtraction() {
         // Parsing should work as expected.
@@ -530,6 +818,88 @@ mod test {
         assert!(matched.vars.is_empty());
     }
 
+    #[test]
+    #[cfg(feature = "url_variables")]
+    fn success_constrained_tree() {
+        // Constrained variables should only match fitting values.
+        let mut node: PathNode<i32> = PathNode::new();
+        node.insert(PathBuf::parse_route("/users/:id<u64>").unwrap(), 1);
+        node.insert(PathBuf::parse_route("/users/:slug([a-z-]+)").unwrap(), 2);
+        node.insert(PathBuf::parse_route("/users/:any").unwrap(), 3);
+        let path1 = PathBuf::parse("/users/42").unwrap();
+        let path2 = PathBuf::parse("/users/jane-doe").unwrap();
+        let path3 = PathBuf::parse("/users/Jane42").unwrap();
+        let mut matched: Vec<i32> = node.get(&path1).iter().map(|v| *v.data).collect();
+        matched.sort_unstable();
+        assert_eq!(matched, vec![1, 3]);
+        let matched = node.get(&path2);
+        assert_eq!(matched.len(), 2);
+        assert!(matched.iter().all(|v| v.vars.len() == 1));
+        assert_eq!(node.get(&path3).remove(0).vars["any"], "Jane42");
+        assert_eq!(node.get(&path3).len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "url_variables")]
+    fn success_wildcard_tree() {
+        // Wildcards should capture the rest of the path.
+        let mut node: PathNode<i32> = PathNode::new();
+        node.insert(PathBuf::parse_route("/files/*rest").unwrap(), 1);
+        node.insert(PathBuf::parse_route("/files/:name").unwrap(), 2);
+        let path1 = PathBuf::parse("/files/a/b//c.txt").unwrap();
+        let path2 = PathBuf::parse("/files/a").unwrap();
+        let path3 = PathBuf::parse("/files").unwrap();
+        let matched = node.get(&path1);
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].vars["rest"], "a/b/c.txt");
+        assert_eq!(node.get(&path2).len(), 2);
+        assert!(node.get(&path3).is_empty());
+    }
+
+    #[test]
+    fn success_specificity() {
+        let rank =
+            |path: &str| -> Vec<u8> { PathBuf::parse_route(path).unwrap().specificity().collect() };
+        assert!(rank("/users/me") < rank("/users/:id"));
+        assert!(rank("/users/:id<u64>") < rank("/users/*rest"));
+        assert!(rank("/files/a/:b") < rank("/files/*rest"));
+    }
+
+    #[test]
+    fn success_rest() {
+        let path = PathBuf::parse("/a/bc/d").unwrap();
+        assert_eq!(path.rest(0), "a/bc/d");
+        assert_eq!(path.rest(2), "d");
+        assert_eq!(path.rest(3), "");
+        assert_eq!(path.concat(&PathBuf::parse("e").unwrap()).rest(1), "bc/d/e");
+    }
+
+    #[test]
+    fn fail_wildcard() {
+        // Wildcards should only be allowed at the end.
+        assert!(PathBuf::parse_route("/files/*rest/more").is_err());
+        assert!(PathBuf::parse_route("/files/*rest").unwrap().has_wildcard());
+    }
+
+    #[test]
+    fn success_split_var() {
+        assert_eq!(split_var(":id"), ("id", None));
+        assert_eq!(split_var(":id<u64>"), ("id", Some("<u64>")));
+        assert_eq!(split_var(":slug([a-z-]+)"), ("slug", Some("([a-z-]+)")));
+        assert_eq!(split_var(":odd<u64"), ("odd<u64", None));
+    }
+
+    #[test]
+    fn fail_constraint() {
+        // Unknown types and invalid regexes should error.
+        assert!(PathBuf::parse_route("/users/:id<u65>").is_err());
+        assert!(PathBuf::parse_route("/users/:id([a-z)").is_err());
+        assert!(PathBuf::parse_route("/users/:id<i8>/:x(a|b)").is_ok());
+        // Specs too short to have brackets shouldn't panic.
+        assert!(Constraint::parse("").is_err());
+        assert!(Constraint::parse("(").is_err());
+    }
+
     #[test]
     #[cfg(not(feature = "url_variables"))]
     fn success_tree() {
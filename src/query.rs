@@ -1,5 +1,20 @@
 use std::collections::HashMap;
 
+// Percent encodes every byte of the string which isn't an
+// unreserved character, the opposite of unescape_hex
+pub fn escape_hex(string: &str) -> String {
+    let mut ret = String::with_capacity(string.len());
+    for byte in string.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                ret.push(byte as char)
+            }
+            _ => ret.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    ret
+}
+
 pub fn unescape_hex(string: &str) -> String {
     let mut ret = "".to_owned();
     let mut chars = string.chars();
@@ -305,6 +320,13 @@ mod test {
         assert_eq!(query["ab"], "ab cdJ");
     }
 
+    #[test]
+    fn success_escape() {
+        // Escaping should be undone by unescaping.
+        assert_eq!(escape_hex("a b/c~d%"), "a%20b%2Fc~d%25");
+        assert_eq!(unescape_hex(&escape_hex("ab cd/J")), "ab cd/J");
+    }
+
     #[test]
     fn success_blank_query() {
         // Queries without an "=" should be blank.
@@ -2,22 +2,28 @@ use crate::constants::*;
 #[cfg(feature = "cookies")]
 use crate::cookie::Cookie;
 use crate::deref;
+pub use crate::error::ParamError;
 use crate::path::is_ctl;
 use crate::path::PathBuf;
 use crate::query::parse_query;
 #[cfg(feature = "extended_queries")]
 use crate::query::{parse_extended_query, QueryValue};
+use crate::state::{Extensions, State};
 use crate::util::Spliterator;
 use std::cfg;
 use std::collections::HashMap;
 #[cfg(not(feature = "raw_headers"))]
 use std::marker::PhantomData;
 use std::str;
+#[cfg(feature = "url_variables")]
+use std::str::FromStr;
 use std::string::ToString;
 
 /// Holds the type of request method, like GET,
-/// POST etc.
-#[derive(Debug, PartialEq, Eq, Clone, Hash, Copy)]
+/// POST etc. Methods which aren't defined by HTTP/1.1
+/// itself, like WebDAV's PROPFIND, are held by the
+/// `Custom` variant
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum RequestMethod {
     #[doc(hidden)]
     Options,
@@ -37,6 +43,8 @@ pub enum RequestMethod {
     Connect,
     #[doc(hidden)]
     Patch,
+    /// An extension method, the name is case sensitive
+    Custom(String),
     #[doc(hidden)]
     All,
     #[doc(hidden)]
@@ -44,6 +52,55 @@ pub enum RequestMethod {
 }
 
 impl RequestMethod {
+    /// Parses a method name, names which aren't one of the
+    /// standard methods are returned as `Custom` and names
+    /// which aren't valid tokens are returned as `None`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::request::RequestMethod;
+    ///
+    /// assert_eq!(RequestMethod::parse("GET"), RequestMethod::Get);
+    /// assert_eq!(
+    ///     RequestMethod::parse("PROPFIND"),
+    ///     RequestMethod::Custom("PROPFIND".to_owned())
+    /// );
+    /// ```
+    pub fn parse(method: &str) -> Self {
+        match method {
+            "POST" => Self::Post,
+            "GET" => Self::Get,
+            "DELETE" => Self::Delete,
+            "PUT" => Self::Put,
+            "OPTIONS" => Self::Options,
+            "HEAD" => Self::Head,
+            "TRACE" => Self::Trace,
+            "CONNECT" => Self::Connect,
+            "PATCH" => Self::Patch,
+            _ if !method.is_empty() && method.chars().all(|c| TOKEN_CHARS.contains(&c)) => {
+                Self::Custom(method.to_owned())
+            }
+            _ => Self::None,
+        }
+    }
+    /// Returns the name of the method as it's sent in
+    /// the request, `All` and `None` return an empty str
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Options => "OPTIONS",
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+            Self::Trace => "TRACE",
+            Self::Connect => "CONNECT",
+            Self::Patch => "PATCH",
+            Self::Custom(method) => method,
+            Self::All | Self::None => "",
+        }
+    }
     /// Return false if the RequestMethod is `None`, otherwise it's true
     pub fn is_some(&self) -> bool {
         !matches!(self, Self::None)
@@ -147,18 +204,7 @@ impl RequestLine {
         if cfg!(feature = "faithful") && (first != "HTTP/" || toks.next().is_some()) {
             return None;
         }
-        let request_method = match method {
-            "POST" => RequestMethod::Post,
-            "GET" => RequestMethod::Get,
-            "DELETE" => RequestMethod::Delete,
-            "PUT" => RequestMethod::Put,
-            "OPTIONS" => RequestMethod::Options,
-            "HEAD" => RequestMethod::Head,
-            "TRACE" => RequestMethod::Trace,
-            "CONNECT" => RequestMethod::Connect,
-            "PATCH" => RequestMethod::Patch,
-            _ => RequestMethod::None,
-        };
+        let request_method = RequestMethod::parse(method);
         Some(Self {
             method: request_method,
             path,
@@ -248,37 +294,86 @@ impl Headers {
     /// Parse all the headers on a request
     pub(crate) fn parse(request: String) -> Option<Self> {
         let toks = Spliterator::new(request.as_bytes(), B_CRLF);
-        let mut headers: HashMap<String, String> = HashMap::new();
-        #[cfg(feature = "raw_headers")]
-        let mut raw_headers: Vec<Header> = Vec::new();
-        for tok in toks {
-            let parsed = Header::parse(match str::from_utf8(tok) {
-                Ok(s) => s.to_owned(),
-                Err(_) => return None,
-            })?;
-            headers
-                .entry(parsed.name.to_ascii_lowercase())
-                .and_modify(|v| *v = format!("{}, {}", v, parsed.value))
-                .or_insert_with(|| parsed.value.to_owned());
-            #[cfg(feature = "raw_headers")]
-            raw_headers.push(parsed);
-        }
-        Some(Self {
-            parsed: headers,
+        let mut headers = Self {
+            parsed: HashMap::new(),
             #[cfg(feature = "raw_headers")]
-            raw: raw_headers,
+            raw: Vec::new(),
             #[cfg(not(feature = "raw_headers"))]
             raw: PhantomData,
-        })
+        };
+        // a request without any headers leaves a single empty token
+        for tok in toks.filter(|tok| !tok.is_empty()) {
+            headers.insert(Header::parse(match str::from_utf8(tok) {
+                Ok(s) => s.to_owned(),
+                Err(_) => return None,
+            })?);
+        }
+        Some(headers)
+    }
+    // Appends the trailers sent after a chunked body, the fields
+    // which change how the request is framed, routed or handled
+    // are dropped as they come after the head was checked
+    pub(crate) fn append_trailers(&mut self, trailers: Self) {
+        #[cfg(feature = "raw_headers")]
+        let trailers = trailers.raw;
+        #[cfg(not(feature = "raw_headers"))]
+        let trailers = trailers
+            .parsed
+            .into_iter()
+            .map(|(name, value)| Header { name, value });
+        for header in trailers {
+            let name = header.name.to_ascii_lowercase();
+            if !FORBIDDEN_TRAILERS.contains(&name.as_str()) && !name.starts_with("if-") {
+                self.insert(header);
+            }
+        }
+    }
+    // Adds a single header, values of headers with the same
+    // name are joined with a comma
+    fn insert(&mut self, header: Header) {
+        self.parsed
+            .entry(header.name.to_ascii_lowercase())
+            .and_modify(|v| *v = format!("{}, {}", v, header.value))
+            .or_insert_with(|| header.value.to_owned());
+        #[cfg(feature = "raw_headers")]
+        self.raw.push(header);
     }
 }
 
+// The fields a trailer can't set, the conditional if-* headers
+// are left out too
+const FORBIDDEN_TRAILERS: [&str; 20] = [
+    "authorization",
+    "cache-control",
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-range",
+    "content-type",
+    "cookie",
+    "expect",
+    "host",
+    "keep-alive",
+    "max-forwards",
+    "pragma",
+    "proxy-authorization",
+    "range",
+    "set-cookie",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
 // Helper function for extracting some headers
 pub(crate) fn parse_without_body(data: &str) -> Option<(RequestLine, Headers)> {
-    let n = data.find("\r\n")?;
-    let (line, rest) = data.split_at(n);
+    // the request line is all there is if no headers were sent
+    let (line, rest) = match data.find("\r\n") {
+        Some(n) => (&data[..n], &data[n + 2..]),
+        None => (data, ""),
+    };
     let request_line = RequestLine::parse(line)?;
-    let headers = Headers::parse((&rest[2..]).to_owned())?;
+    let headers = Headers::parse(rest.to_owned())?;
     Some((request_line, headers))
 }
 
@@ -412,7 +507,6 @@ impl KeepAlive {
     pub fn timeout(&self) -> Option<u64> {
         self.timeout
     }
-    #[allow(dead_code)]
     // Returns the amount of max requests specified in the keep alive header
     pub fn max(&self) -> Option<u64> {
         self.max
@@ -443,13 +537,18 @@ impl KeepAlive {
 /// ```
 /// The struct also has the values of the url variables. This
 /// requires the feature `url_variables` to be enabled.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Clone)]
 pub struct MatchedRequest<'a> {
     /// The request coming from the client
     pub request: Request<'a>,
     #[cfg(feature = "url_variables")]
     /// A Hashmap containing the variables specified in the url with their
-    /// respective keys.
+    /// respective keys. A variable can be constrained with a type like
+    /// `:id<u64>` or with a regex like `:slug([a-z-]+)`, the route only
+    /// matches when the value fits the constraint. Regexes can't contain
+    /// a `/` as they are matched against a single chunk of the path.
+    /// A wildcard like `*rest` at the end of the route matches one or more
+    /// chunks, the variable holds all of them joined with slashes.
     ///
     /// ```
     /// use octane::prelude::*;
@@ -465,6 +564,86 @@ pub struct MatchedRequest<'a> {
     /// );
     /// ```
     pub vars: HashMap<&'a str, &'a str>,
+    pub(crate) state: &'a State,
+    pub(crate) extensions: Extensions,
+}
+
+// The state is shared by every request and the extensions can't
+// be compared, so requests are equal when what was sent is
+impl PartialEq for MatchedRequest<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "url_variables")]
+        if self.vars != other.vars {
+            return false;
+        }
+        self.request == other.request
+    }
+}
+
+impl Eq for MatchedRequest<'_> {}
+
+impl<'a> MatchedRequest<'a> {
+    /// Borrows the value of the type T from the application
+    /// state, returns None if no value of that type was given
+    /// to [`app.manage()`](../struct.Octane.html#method.manage)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// struct Visits(std::sync::atomic::AtomicUsize);
+    ///
+    /// let mut app = Octane::new();
+    /// app.manage(Visits(Default::default()));
+    /// app.get(
+    ///     "/",
+    ///     route!(|req, res| {
+    ///         let visits = req.state::<Visits>().unwrap();
+    ///         let count = visits.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    ///         res.send(format!("Visit number {}", count + 1));
+    ///         Flow::Stop
+    ///     }),
+    /// );
+    /// ```
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&'a T> {
+        self.state.get()
+    }
+    /// Parses the value of the url variable with the given name,
+    /// the error tells if the variable is missing or if it
+    /// couldn't be parsed. Constraints like `:id<u64>` in the
+    /// route make sure that only valid values reach the closure
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.get(
+    ///     "/users/:id<u64>",
+    ///     route!(|req, res| {
+    ///         let id: u64 = req.param("id").unwrap();
+    ///         res.send(format!("User number {}", id));
+    ///         Flow::Stop
+    ///     }),
+    /// );
+    /// ```
+    #[cfg(feature = "url_variables")]
+    pub fn param<T: FromStr>(&self, name: &str) -> Result<T, ParamError<T::Err>> {
+        self.vars
+            .get(name)
+            .ok_or(ParamError::Missing)?
+            .parse()
+            .map_err(ParamError::Invalid)
+    }
+    /// Returns the extensions of this request, values inserted
+    /// into them by a closure can be read by all the closures
+    /// which run after it for the same request. See
+    /// [`Extensions`](../state/struct.Extensions.html) for an example
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
 }
 
 deref!(MatchedRequest<'a>, Request<'a>, request);
@@ -495,6 +674,8 @@ mod test {
     fn sucess_non_documented() {
         // Non-documented methods should also work.
         let req = RequestLine::parse("XYZ /abc/def HTTP/1.1").unwrap();
+        assert_eq!(req.method, RequestMethod::Custom("XYZ".to_owned()));
+        let req = RequestLine::parse("X(Z /abc/def HTTP/1.1").unwrap();
         assert_eq!(req.method, RequestMethod::None);
         assert_eq!(req.path, PathBuf::parse("/abc/def").ok().unwrap());
         assert_eq!(req.version, HttpVersion::Http11);
@@ -556,6 +737,47 @@ mod test {
         );
     }
 
+    #[test]
+    fn success_matched_request_eq() {
+        // A cloned request should equal the original.
+        let (request_line, headers) = parse_without_body("GET / HTTP/1.1\r\nHost: x").unwrap();
+        let state = State::new();
+        let matched = MatchedRequest {
+            request: Request::parse(request_line, &headers, b"").unwrap(),
+            #[cfg(feature = "url_variables")]
+            vars: HashMap::new(),
+            state: &state,
+            extensions: Extensions::new(),
+        };
+        assert_eq!(matched, matched.clone());
+    }
+
+    #[test]
+    fn success_trailers() {
+        // Trailers shouldn't be able to change how the request is handled.
+        let mut headers = Headers::parse("Host: x\r\nContent-Length: 3".to_string()).unwrap();
+        let trailers = Headers::parse(
+            "Content-Length: 10\r\nHOST: y\r\nTransfer-Encoding: gzip\r\n\
+        If-Match: *\r\nX-Checksum: abc"
+                .to_string(),
+        )
+        .unwrap();
+        headers.append_trailers(trailers);
+        assert_eq!(headers.get("content-length").unwrap(), "3");
+        assert_eq!(headers.get("host").unwrap(), "x");
+        assert!(!headers.contains_key("transfer-encoding"));
+        assert!(!headers.contains_key("if-match"));
+        assert_eq!(headers.get("x-checksum").unwrap(), "abc");
+    }
+
+    #[test]
+    fn success_no_headers() {
+        // A request line without any headers should parse
+        let (reqline, headers) = parse_without_body("GET / HTTP/1.0").unwrap();
+        assert_eq!(reqline.version, HttpVersion::Http10);
+        assert!(headers.is_empty());
+    }
+
     #[test]
     #[cfg(feature = "raw_headers")]
     fn success_raw_headers() {
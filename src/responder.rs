@@ -2,6 +2,7 @@ use crate::constants::*;
 #[cfg(feature = "cookies")]
 use crate::cookie::Cookie;
 use crate::file_handler::FileHandler;
+use crate::http::chunked::ChunkedEncoder;
 use crate::request::HttpVersion;
 use crate::time::Time;
 use octane_json::convert::ToJSON;
@@ -10,6 +11,7 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::io::Cursor;
+use std::mem;
 use std::path::PathBuf;
 use tokio::io::AsyncRead;
 
@@ -31,6 +33,27 @@ impl ResBody {
     pub fn is_some(&self) -> bool {
         !matches!(self, ResBody::None)
     }
+    // Tells if the client can find the end of the body without
+    // the connection being closed
+    pub fn is_delimited(&self, http_version: &str) -> bool {
+        !matches!(self, ResBody::Unsized(_)) || http_version == "1.1"
+    }
+    // Sets the headers that tell the client where the body ends and
+    // returns the reader the body should be sent from. Unsized bodies
+    // are chunked on http 1.1 and end with the connection otherwise
+    pub fn frame(self, headers: &mut HashMap<String, String>, http_version: &str) -> BoxReader {
+        match self {
+            ResBody::Sized(len, reader) => {
+                headers.insert("Content-Length".to_string(), len.to_string());
+                reader
+            }
+            ResBody::Unsized(reader) if http_version == "1.1" => {
+                headers.insert("Transfer-Encoding".to_string(), "chunked".to_string());
+                Box::new(ChunkedEncoder::new(reader)) as BoxReader
+            }
+            body => body.get_reader(),
+        }
+    }
 }
 #[cfg(feature = "cookies")]
 /// The response struct contains the data which is
@@ -189,10 +212,12 @@ impl<'a> Response<'a> {
     }
     /// Consume the response and get the final formed http
     /// response that the server will send in bytes
-    pub fn get_data(self) -> (String, BoxReader) {
+    pub fn get_data(mut self) -> (String, BoxReader) {
+        let body = mem::replace(&mut self.body, ResBody::None)
+            .frame(&mut self.headers, &self.http_version);
         (
             format!("{}{}{}", self.status_line(), self.headers(), CRLF),
-            self.body.get_reader(),
+            body,
         )
     }
     /// Send a file as the response, automatically detect the
@@ -435,6 +460,11 @@ impl<'a> Response<'a> {
     pub(crate) fn has_body(&self) -> bool {
         self.body.is_some()
     }
+    // Tells if the end of the body can be found by the client
+    // while keeping the connection open
+    pub(crate) fn is_delimited(&self) -> bool {
+        self.body.is_delimited(&self.http_version)
+    }
     // Creates a new response from a slice
     pub(crate) fn new_from_slice<T: AsRef<[u8]>>(body: T) -> Self {
         let body_slice = body.as_ref();
@@ -498,14 +528,9 @@ impl<'a> Response<'a> {
             .iter()
             .for_each(|data| headers_str.push_str(&format!("{}:{}{}{}", data.0, SP, data.1, CRLF)));
         // push cookies
-        headers_str.push_str(
-            &self
-                .cookies
-                .iter()
-                .map(|cookie| cookie.serialise())
-                .collect::<Vec<String>>()
-                .join(CRLF),
-        );
+        self.cookies
+            .iter()
+            .for_each(|cookie| headers_str.push_str(&format!("{}{}", cookie.serialise(), CRLF)));
         headers_str
     }
 }
@@ -664,10 +689,12 @@ impl Response {
     }
     /// Consume the response and get the final formed http
     /// response that the server will send in bytes
-    pub fn get_data(self) -> (String, BoxReader) {
+    pub fn get_data(mut self) -> (String, BoxReader) {
+        let body = mem::replace(&mut self.body, ResBody::None)
+            .frame(&mut self.headers, &self.http_version);
         (
             format!("{}{}{}", self.status_line(), self.headers(), CRLF),
-            self.body.get_reader(),
+            body,
         )
     }
     /// Send a file as the response, automatically detect the
@@ -886,6 +913,11 @@ impl Response {
     pub(crate) fn has_body(&self) -> bool {
         self.body.is_some()
     }
+    // Tells if the end of the body can be found by the client
+    // while keeping the connection open
+    pub(crate) fn is_delimited(&self) -> bool {
+        self.body.is_delimited(&self.http_version)
+    }
     // Creates a new response from a slice
     pub(crate) fn new_from_slice<T: AsRef<[u8]>>(body: T) -> Self {
         let body_slice = body.as_ref();
@@ -1080,7 +1112,7 @@ mod test {
     async fn success_standard() {
         // default response should provide OK 200 Code
         let req = data_to_string(Response::new_from_slice(b"").get_data()).await;
-        assert_eq!(req, "HTTP/1.1 200 OK\r\n\r\n");
+        assert_eq!(req, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
     }
 
     #[crate::test]
@@ -1091,7 +1123,26 @@ mod test {
 
         assert_eq!(
             data_to_string(req.get_data()).await,
-            "HTTP/1.1 201 CREATED\r\n\r\n"
+            "HTTP/1.1 201 CREATED\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    #[crate::test]
+    async fn response_unsized_body() {
+        // Bodies without a length should be chunked on http 1.1
+        let req = Response::new(Box::new(Cursor::new(b"Hello".to_vec())), None);
+        assert!(req.is_delimited());
+        assert_eq!(
+            data_to_string(req.get_data()).await,
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n0\r\n\r\n"
+        );
+        // and end with the connection on http 1.0
+        let mut req = Response::new(Box::new(Cursor::new(b"Hello".to_vec())), None);
+        req.http_version(HttpVersion::Http10);
+        assert!(!req.is_delimited());
+        assert_eq!(
+            data_to_string(req.get_data()).await,
+            "HTTP/1.0 200 OK\r\n\r\nHello"
         );
     }
 
@@ -1104,7 +1155,7 @@ mod test {
             .status(StatusCode::Created);
         assert_eq!(
             data_to_string(req.get_data()).await,
-            "HTTP/1.0 201 CREATED\r\n\r\n"
+            "HTTP/1.0 201 CREATED\r\nContent-Length: 0\r\n\r\n"
         );
     }
 }
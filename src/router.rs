@@ -1,17 +1,164 @@
 use crate::default;
 use crate::error::InvalidPathError;
+pub use crate::error::UrlError;
 use crate::middlewares::Closures;
-use crate::path::{MatchedPath, PathNode};
+use crate::path::{split_var, MatchedPath, PathBuf, PathData, PathNode};
+use crate::query::escape_hex;
 use crate::request::{MatchedRequest, Request, RequestMethod};
-use crate::responder::Response;
+use crate::responder::{Response, StatusCode};
+use crate::state::{Extensions, State};
+use std::any::Any;
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
 use std::result::Result;
+use std::task::{Context, Poll};
 
 // The type of HashMap where we will be storing the all the closures
 pub(crate) type Paths = HashMap<RequestMethod, PathNode<Closures>>;
 /// The Closure type is a type alias for the type
 /// that the routes should return
 pub type Closure = Box<dyn for<'a> Fn(&'a MatchedRequest, &'a mut Response) -> Flow + Send + Sync>;
+/// A boxed future which is returned by async closures, it can
+/// borrow the request and response for as long as it runs
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+/// The AsyncClosure type is the async counterpart of
+/// [`Closure`](type.Closure.html), the returned future is awaited
+/// before the next closure runs. See [`route_async`](../macro.route_async.html)
+pub type AsyncClosure =
+    Box<dyn for<'a> Fn(&'a MatchedRequest, &'a mut Response) -> BoxFuture<'a, Flow> + Send + Sync>;
+/// The error a fallible closure can fail with, any error type
+/// converts into it with the `?` operator
+pub type RouteError = Box<dyn std::error::Error + Send + Sync>;
+/// The TryClosure type is the fallible version of
+/// [`Closure`](type.Closure.html), when it returns an error the
+/// remaining closures are skipped and the error is handed to the
+/// error handlers. See [`route_try`](../macro.route_try.html)
+pub type TryClosure = Box<
+    dyn for<'a> Fn(&'a MatchedRequest, &'a mut Response) -> Result<Flow, RouteError> + Send + Sync,
+>;
+/// The AsyncTryClosure type is the async counterpart of
+/// [`TryClosure`](type.TryClosure.html). See
+/// [`route_try_async`](../macro.route_try_async.html)
+pub type AsyncTryClosure = Box<
+    dyn for<'a> Fn(&'a MatchedRequest, &'a mut Response) -> BoxFuture<'a, Result<Flow, RouteError>>
+        + Send
+        + Sync,
+>;
+/// The ErrorClosure type is the type of the error handlers added
+/// with [`on_error()`](struct.Router.html#method.on_error), they
+/// get the error along with the request and the response. See
+/// [`route_error`](../macro.route_error.html)
+pub type ErrorClosure =
+    Box<dyn for<'a> Fn(&'a RouteError, &'a MatchedRequest, &'a mut Response) -> Flow + Send + Sync>;
+/// Handler holds either a sync or an async closure, which may
+/// be fallible, the app.METHOD methods accept anything that can
+/// be converted into it
+pub enum Handler {
+    /// A closure which runs to completion when called
+    Sync(Closure),
+    /// A closure which returns a future that has to be awaited
+    Async(AsyncClosure),
+    /// A closure which runs to completion and can fail
+    TrySync(TryClosure),
+    /// A closure which returns a future that can fail
+    TryAsync(AsyncTryClosure),
+}
+
+impl Handler {
+    // Run the closure, the returned future finishes once the
+    // closure is done
+    pub(crate) fn call<'a>(
+        &'a self,
+        req: &'a MatchedRequest,
+        res: &'a mut Response,
+    ) -> BoxFuture<'a, Result<Flow, RouteError>> {
+        match self {
+            // sync closures run when the future is polled so a
+            // panic in them can be caught like in async ones
+            Handler::Sync(closure) => Box::pin(async move { Ok(closure(req, res)) }),
+            Handler::Async(closure) => {
+                let future = closure(req, res);
+                Box::pin(async move { Ok(future.await) })
+            }
+            Handler::TrySync(closure) => Box::pin(async move { closure(req, res) }),
+            Handler::TryAsync(closure) => closure(req, res),
+        }
+    }
+}
+
+// Polls the future of a closure, a panic while polling is
+// returned as an error instead of unwinding further
+struct CatchUnwind<'a, T>(BoxFuture<'a, T>);
+
+impl<T> Future for CatchUnwind<'_, T> {
+    type Output = std::thread::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = &mut self.0;
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(v)) => Poll::Ready(Ok(v)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+/// RoutePanic is handed to the hook added with
+/// [`on_panic()`](struct.Router.html#method.on_panic) when a
+/// closure panics while handling a request
+pub struct RoutePanic {
+    /// The value the closure panicked with
+    pub payload: Box<dyn Any + Send>,
+    /// The pattern of the route or middleware which panicked
+    pub pattern: String,
+}
+
+impl RoutePanic {
+    /// Returns the panic message if the closure panicked with
+    /// a string, like `panic!()` and `unwrap()` do
+    pub fn message(&self) -> Option<&str> {
+        self.payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+}
+
+impl fmt::Debug for RoutePanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoutePanic")
+            .field("message", &self.message())
+            .field("pattern", &self.pattern)
+            .finish()
+    }
+}
+
+impl From<Closure> for Handler {
+    fn from(closure: Closure) -> Self {
+        Handler::Sync(closure)
+    }
+}
+
+impl From<AsyncClosure> for Handler {
+    fn from(closure: AsyncClosure) -> Self {
+        Handler::Async(closure)
+    }
+}
+
+impl From<TryClosure> for Handler {
+    fn from(closure: TryClosure) -> Self {
+        Handler::TrySync(closure)
+    }
+}
+
+impl From<AsyncTryClosure> for Handler {
+    fn from(closure: AsyncTryClosure) -> Self {
+        Handler::TryAsync(closure)
+    }
+}
 // RouterResult is the type which the app.METHOD methods return
 pub(crate) type RouterResult = Result<(), InvalidPathError>;
 /// The flow enum works just like the next() callback
@@ -79,7 +226,7 @@ pub trait Route {
     /// add_route() is a duplicate of add() but with a
     /// specified url on where it should run.
     /// It runs on the given path and on all types of requests
-    fn add_route(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn add_route(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
     /// Part of app.METHOD, runs on when the request is on the
     /// path given and the request method is HEAD
     ///
@@ -99,7 +246,7 @@ pub trait Route {
     ///     ),
     /// );
     /// ```
-    fn head(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn head(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
     /// Part of app.METHOD, runs on when the request is on the
     /// path given and the request method is POST
     ///
@@ -119,7 +266,7 @@ pub trait Route {
     ///     ),
     /// );
     /// ```
-    fn post(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn post(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
     /// Part of app.METHOD, runs on when the request is on the
     /// path given and the request method is GET
     ///
@@ -139,7 +286,7 @@ pub trait Route {
     ///     ),
     /// );
     /// ```
-    fn get(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn get(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
     /// Part of app.METHOD, runs on when the request is on the
     /// path given and the request method is PUT
     ///
@@ -159,7 +306,7 @@ pub trait Route {
     ///     ),
     /// );
     /// ```
-    fn put(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn put(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
     /// Part of app.METHOD, runs on when the request is on the
     /// path given and the request method is DELETE
     ///
@@ -179,7 +326,7 @@ pub trait Route {
     ///     ),
     /// );
     /// ```
-    fn delete(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn delete(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
     /// Part of app.METHOD, runs on when the request is on the
     /// path given and the request method is PATCH
     ///
@@ -199,11 +346,133 @@ pub trait Route {
     ///     ),
     /// );
     /// ```
-    fn patch(&mut self, path: &str, closure: Closure) -> RouterResult;
+    fn patch(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
+    /// Part of app.METHOD, runs on when the request is on the
+    /// path given and the request method is OPTIONS
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.options(
+    ///     "/",
+    ///     route!(
+    ///         |req, res| {
+    ///             res.send("Hello, World");
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// ```
+    fn options(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
+    /// Part of app.METHOD, runs on when the request is on the
+    /// path given and the request method is TRACE
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.trace(
+    ///     "/",
+    ///     route!(
+    ///         |req, res| {
+    ///             res.send("Hello, World");
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// ```
+    fn trace(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
+    /// Part of app.METHOD, runs on when the request is on the
+    /// path given and the request method is CONNECT
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.connect(
+    ///     "/",
+    ///     route!(
+    ///         |req, res| {
+    ///             res.send("Hello, World");
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// ```
+    fn connect(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult;
+    /// Runs when the request is on the path given and the
+    /// request method is the one given, this is how closures
+    /// are added for extension methods which don't have an
+    /// app.METHOD of their own
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut app = Octane::new();
+    /// app.route(
+    ///     RequestMethod::parse("PROPFIND"),
+    ///     "/",
+    ///     route!(
+    ///         |req, res| {
+    ///             res.send("Hello, World");
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// ```
+    fn route(
+        &mut self,
+        method: RequestMethod,
+        path: &str,
+        closure: impl Into<Handler>,
+    ) -> RouterResult;
+    /// Same as [`route()`](#tymethod.route) but the route also
+    /// gets a name, the url of the route can then be built from
+    /// the name with `url_for()` so links don't break when the
+    /// route moves. Names should be unique, if they aren't then
+    /// `url_for()` uses the route which was added first
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut app = Octane::new();
+    /// app.route_named(
+    ///     "user_detail",
+    ///     RequestMethod::Get,
+    ///     "/users/:id",
+    ///     route!(
+    ///         |req, res| {
+    ///             res.send("Hello, World");
+    ///             Flow::Stop
+    ///         }
+    ///     ),
+    /// );
+    /// assert_eq!(app.url_for("user_detail", &[("id", "5")]).unwrap(), "/users/5");
+    /// ```
+    fn route_named(
+        &mut self,
+        name: &str,
+        method: RequestMethod,
+        path: &str,
+        closure: impl Into<Handler>,
+    ) -> RouterResult;
     /// add() is like `app.use` in express, it runs on all the
     /// paths and all types of valid methods, the request comes
     /// on
-    fn add(&mut self, entity: Closure) -> RouterResult;
+    fn add(&mut self, entity: impl Into<Handler>) -> RouterResult;
 }
 
 /// The router structure defines the routes and stores them along with
@@ -212,10 +481,64 @@ pub trait Route {
 pub struct Router {
     /// Holds a counter and increments on new additions of routes
     pub route_counter: usize,
-    /// A vector of middleware closures
-    pub middlewares: Vec<Closures>,
+    /// A vector of middleware closures, along with the path
+    /// prefix they are scoped to
+    pub middlewares: Vec<PathData<Closures>>,
     /// The router paths which are to be executed on requests
     pub paths: Paths,
+    /// The application state which the closures can borrow
+    pub state: State,
+    /// The order in which the closures matching a request run
+    pub match_order: MatchOrder,
+    /// The error handlers, along with the path prefix they are
+    /// scoped to
+    pub error_handlers: Vec<PathData<ErrorClosure>>,
+    /// The hook which is told about closures that panic
+    pub panic_hook: Option<Box<dyn Fn(&RoutePanic) + Send + Sync>>,
+}
+
+/// RouteKind tells how a closure listed by
+/// [`routes()`](struct.Router.html#method.routes) was added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteKind {
+    /// Added with app.METHOD, `route()` or `add_route()`
+    Route,
+    /// Added with `add()`, it runs on every path under its pattern
+    Middleware,
+}
+
+/// MatchOrder decides the order in which the closures that
+/// match a request are run, the middlewares added with `add`
+/// always run in the order they were added
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+/// use octane::router::MatchOrder;
+///
+/// let mut app = Octane::new();
+/// app.match_order(MatchOrder::Specificity);
+/// app.get("/users/:id", route!(|req, res| {
+///     res.send("Some user");
+///     Flow::Stop
+/// }));
+/// // runs first on /users/me even though it was added later
+/// app.get("/users/me", route!(|req, res| {
+///     res.send("You");
+///     Flow::Stop
+/// }));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOrder {
+    /// The closures run in the order they were added, this is
+    /// the default
+    Registration,
+    /// Routes with static chunks run before routes with url
+    /// variables at the same place, which run before routes
+    /// with wildcards. Routes which are as specific as each
+    /// other run in the order they were added
+    Specificity,
 }
 
 impl Router {
@@ -228,6 +551,296 @@ impl Router {
             route_counter: 0,
             middlewares: Vec::new(),
             paths: HashMap::new(),
+            state: State::new(),
+            match_order: MatchOrder::Registration,
+            error_handlers: Vec::new(),
+            panic_hook: None,
+        }
+    }
+    /// Sets the order in which the closures matching a request
+    /// run, see [`MatchOrder`](enum.MatchOrder.html). The order
+    /// of a router given to `mount()` or `with_router()` is not
+    /// kept, its routes run in the order of the router they are
+    /// added to.
+    pub fn match_order(&mut self, order: MatchOrder) {
+        self.match_order = order;
+    }
+    /// Stores a value in the router state, it can then be
+    /// borrowed from the closures with
+    /// [`req.state()`](../request/struct.MatchedRequest.html#method.state).
+    /// Only one value of each type is kept, managing a value
+    /// of a type twice replaces the previous one.
+    ///
+    /// The state is a single map shared by every route of the
+    /// app, so when a router is appended or mounted its values
+    /// are only added for the types the parent doesn't manage,
+    /// the parent owns the app and decides over shared types.
+    /// Wrap a value in a newtype to keep it to the routes of the
+    /// router.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut router = Router::new();
+    /// router.manage(String::from("Hello, World"));
+    /// router.get(
+    ///     "/",
+    ///     route!(|req, res| {
+    ///         res.send(req.state::<String>().unwrap().clone());
+    ///         Flow::Stop
+    ///     }),
+    /// );
+    /// ```
+    pub fn manage<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.state.insert(value);
+    }
+    /// Adds an error handler, it runs when a fallible closure
+    /// returns an error. The error handlers run in the order they
+    /// were added until one of them returns `Flow::Stop`, if none
+    /// of them does then a 500 error page is sent. The error
+    /// handlers of a router given to `mount()` or `with_router()`
+    /// run before the ones of the router it was added to, and only
+    /// for the paths under the prefix it was mounted on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::responder::StatusCode;
+    ///
+    /// let mut router = Router::new();
+    /// router.get(
+    ///     "/users/:id",
+    ///     route_try!(|req, res| {
+    ///         let id: u64 = req.param("id")?;
+    ///         res.send(format!("User {}", id));
+    ///         Ok(Flow::Stop)
+    ///     }),
+    /// );
+    /// router.on_error(route_error!(|err, req, res| {
+    ///     res.status(StatusCode::BadRequest)
+    ///         .send(format!("Bad request: {}", err));
+    ///     Flow::Stop
+    /// }));
+    /// ```
+    pub fn on_error(&mut self, handler: ErrorClosure) {
+        self.error_handlers.push(PathData {
+            orig_path: PathBuf::new(),
+            data: handler,
+        });
+    }
+    /// Sets the hook which runs when a closure panics while
+    /// handling a request, it gets the panic payload along with
+    /// the pattern of the route. The panic doesn't take the
+    /// connection down, the remaining closures are skipped and a
+    /// 500 error page is sent. Setting a hook again replaces the
+    /// previous one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/", route!(|req, res| {
+    ///     panic!("Something went wrong");
+    /// }));
+    /// router.on_panic(|panic| {
+    ///     eprintln!("{} panicked: {:?}", panic.pattern, panic.message());
+    /// });
+    /// ```
+    pub fn on_panic<F>(&mut self, hook: F)
+    where
+        F: Fn(&RoutePanic) + Send + Sync + 'static,
+    {
+        self.panic_hook = Some(Box::new(hook));
+    }
+    /// Mounts another router under a path prefix, the routes of
+    /// that router are moved under the prefix and its middlewares
+    /// only run for the paths which start with the prefix. The
+    /// prefix can contain url variables which the closures of the
+    /// mounted router can read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut users = Router::new();
+    /// // runs only for the paths under /users/:id
+    /// users.add(route_next!(|req, res| {
+    ///     res.set("X-User", req.vars.get("id").unwrap());
+    /// }));
+    /// // handles /users/:id/posts
+    /// users.get("/posts", route!(|req, res| {
+    ///     res.send("The posts");
+    ///     Flow::Stop
+    /// }));
+    ///
+    /// let mut api = Router::new();
+    /// api.mount("/users/:id", users).unwrap();
+    /// ```
+    pub fn mount(&mut self, prefix: &str, router: Self) -> RouterResult {
+        let prefix = PathBuf::parse_route(prefix)?;
+        // nothing can come after a wildcard
+        if prefix.has_wildcard() {
+            return Err(InvalidPathError);
+        }
+        self.append(router.prefixed(&prefix));
+        Ok(())
+    }
+    // move every route and middleware of the router under the prefix
+    fn prefixed(mut self, prefix: &PathBuf) -> Self {
+        self.paths = self
+            .paths
+            .into_iter()
+            .map(|(method, paths)| {
+                let updated_paths = paths.into_iter().map(|mut v| {
+                    v.orig_path = prefix.concat(&v.orig_path);
+                    v
+                });
+                (method, updated_paths.collect())
+            })
+            .collect();
+        for middleware in self.middlewares.iter_mut() {
+            middleware.orig_path = prefix.concat(&middleware.orig_path);
+        }
+        for handler in self.error_handlers.iter_mut() {
+            handler.orig_path = prefix.concat(&handler.orig_path);
+        }
+        self
+    }
+    /// Lists every route and middleware of the router as
+    /// `(method, pattern, index, kind)` in the order they were
+    /// added. Middlewares and routes added with `add_route()`
+    /// have the method `RequestMethod::All`, the pattern of a
+    /// middleware is the prefix it was mounted under
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    /// use octane::router::RouteKind;
+    ///
+    /// let mut router = Router::new();
+    /// router.add(route_next!(|req, res| {}));
+    /// router.get("/users/:id", route!(|req, res| { Flow::Stop }));
+    /// let routes: Vec<_> = router.routes().collect();
+    /// assert_eq!(
+    ///     routes,
+    ///     vec![
+    ///         (&RequestMethod::All, "/".to_owned(), 0, RouteKind::Middleware),
+    ///         (&RequestMethod::Get, "/users/:id".to_owned(), 1, RouteKind::Route),
+    ///     ]
+    /// );
+    /// for (method, pattern, _, _) in router.routes() {
+    ///     println!("{:?} {}", method, pattern);
+    /// }
+    /// ```
+    pub fn routes(&self) -> impl Iterator<Item = (&RequestMethod, String, usize, RouteKind)> {
+        let mut routes: Vec<_> = self
+            .paths
+            .iter()
+            .flat_map(|(method, node)| {
+                node.iter()
+                    .map(move |v| (method, v.orig_path.pattern(), v.index, RouteKind::Route))
+            })
+            .chain(self.middlewares.iter().map(|v| {
+                (
+                    &RequestMethod::All,
+                    v.orig_path.pattern(),
+                    v.index,
+                    RouteKind::Middleware,
+                )
+            }))
+            .collect();
+        routes.sort_by_key(|v| v.2);
+        routes.into_iter()
+    }
+    /// Builds the url of the route with the given name, the url
+    /// variables are filled with the values given for them
+    /// which are percent encoded. The values of wildcards can
+    /// contain slashes, each chunk of them is encoded on its own.
+    /// Routes of mounted routers include the prefix they were
+    /// mounted under.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    /// use octane::router::UrlError;
+    ///
+    /// let mut router = Router::new();
+    /// router.route_named(
+    ///     "user_post",
+    ///     RequestMethod::Get,
+    ///     "/users/:id<u64>/posts/:title",
+    ///     route!(|req, res| { Flow::Next }),
+    /// );
+    /// assert_eq!(
+    ///     router.url_for("user_post", &[("id", "5"), ("title", "Hello World")]),
+    ///     Ok("/users/5/posts/Hello%20World".to_owned())
+    /// );
+    /// assert_eq!(
+    ///     router.url_for("user_post", &[("id", "5")]),
+    ///     Err(UrlError::MissingParam("title".to_owned()))
+    /// );
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let route = self
+            .paths
+            .values()
+            .flat_map(PathNode::iter)
+            .filter(|v| v.data.name.as_deref() == Some(name))
+            .min_by_key(|v| v.data.index)
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_owned()))?;
+        let mut url = String::new();
+        for (n, chunk) in route.orig_path.iter().enumerate() {
+            url.push('/');
+            let var = match chunk.as_bytes()[0] {
+                b':' => split_var(chunk).0,
+                b'*' => &chunk[1..],
+                _ => {
+                    url.push_str(chunk);
+                    continue;
+                }
+            };
+            let value = params
+                .iter()
+                .find(|(k, _)| *k == var)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| UrlError::MissingParam(var.to_owned()))?;
+            let valid = match route.orig_path.constraint(n) {
+                Some(constraint) => constraint.matches(value),
+                None => !value.is_empty(),
+            };
+            if !valid {
+                return Err(UrlError::InvalidParam(var.to_owned()));
+            }
+            if chunk.starts_with('*') {
+                let chunks: Vec<String> = value.split('/').map(escape_hex).collect();
+                url.push_str(&chunks.join("/"));
+            } else {
+                url.push_str(&escape_hex(value));
+            }
+        }
+        if url.is_empty() {
+            url.push('/');
+        }
+        Ok(url)
+    }
+    // Extension methods are only implemented when a path was
+    // added for them or for all methods, other methods always are
+    pub(crate) fn implements(&self, method: &RequestMethod) -> bool {
+        match method {
+            RequestMethod::Custom(_) => {
+                self.paths.contains_key(method) || self.paths.contains_key(&RequestMethod::All)
+            }
+            _ => method.is_some(),
         }
     }
     // append the routes stored in a custom Router to the self Router
@@ -248,71 +861,208 @@ impl Router {
 
         self.middlewares
             .extend(router.middlewares.into_iter().map(|mut v| {
-                v.index += self_count;
+                v.data.index += self_count;
                 v
             }));
         self.route_counter += other_count;
+        // the error handlers of the appended router run first
+        let error_handlers = std::mem::take(&mut self.error_handlers);
+        self.error_handlers = router.error_handlers;
+        self.error_handlers.extend(error_handlers);
+        if self.panic_hook.is_none() {
+            self.panic_hook = router.panic_hook;
+        }
+        // the routes are ordered together, so the match_order of
+        // self applies to the appended ones too
+        // there is one state for all the routes, values managed by
+        // self take precedence (see manage)
+        self.state.append(router.state);
     }
 
     // Fetch the closure according to the request path, run that
     // specific closure.
-    pub(crate) fn run(&self, parsed_request: Request<'_>, mut res: &mut Response) {
-        let req = &parsed_request.request_line;
-
-        let mut matches: Vec<Vec<MatchedPath<Closures>>> = Vec::new();
-        if let Some(functions) = self.paths.get(&req.method) {
-            let mut routes = functions.get(&req.path);
-            routes.sort_by_key(|v| v.index);
-            matches.push(routes);
-        };
-        // run RequestMethod::All regardless of the request method
-        if let Some(functions) = self.paths.get(&RequestMethod::All) {
-            let mut routes = functions.get(&req.path);
-            routes.sort_by_key(|v| v.index);
-            matches.push(routes);
-        }
+    pub(crate) fn run<'a>(
+        &'a self,
+        parsed_request: Request<'a>,
+        res: &'a mut Response,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let req = &parsed_request.request_line;
 
-        matches.push(
-            self.middlewares
-                .iter()
-                .map(|c| MatchedPath {
-                    data: c,
-                    #[cfg(feature = "url_variables")]
-                    vars: HashMap::new(),
-                })
-                .collect(),
-        );
+            let mut matches: Vec<Vec<MatchedPath<Closures>>> = Vec::new();
+            // HEAD requests run the GET closures when the path has no
+            // HEAD closures of its own, the body is dropped when sending
+            let method =
+                if req.method == RequestMethod::Head && !self.has_route(&req.method, &req.path) {
+                    &RequestMethod::Get
+                } else {
+                    &req.method
+                };
+            if let Some(functions) = self.paths.get(method) {
+                let mut routes = functions.get(&req.path);
+                routes.sort_by_key(|v| v.index);
+                matches.push(routes);
+            };
+            // run RequestMethod::All regardless of the request method
+            if let Some(functions) = self.paths.get(&RequestMethod::All) {
+                let mut routes = functions.get(&req.path);
+                routes.sort_by_key(|v| v.index);
+                matches.push(routes);
+            }
+            let routed = matches.iter().any(|v| !v.is_empty());
 
-        let mut indices = vec![0_usize; matches.len()];
-        let total: usize = matches.iter().map(Vec::len).sum();
-        #[cfg(feature = "url_variables")]
-        let mut matched = MatchedRequest {
-            request: parsed_request.clone(),
-            vars: HashMap::new(),
-        };
-        #[cfg(not(feature = "url_variables"))]
-        let matched = MatchedRequest {
-            request: parsed_request.clone(),
-        };
-        for _ in 0..total {
-            let mut minind = 0;
-            let mut minval = usize::MAX;
-            for (n, (v, i)) in matches.iter().zip(indices.iter()).enumerate() {
-                if *i < v.len() && v[*i].index < minval {
-                    minval = v[*i].index;
-                    minind = n;
+            matches.push(
+                self.middlewares
+                    .iter()
+                    .filter_map(|c| {
+                        // middlewares of mounted routers are scoped to their prefix
+                        #[cfg(feature = "url_variables")]
+                        let vars = c.orig_path.check_matches_prefix(&req.path)?;
+                        #[cfg(not(feature = "url_variables"))]
+                        c.orig_path.check_matches_prefix(&req.path)?;
+                        Some(MatchedPath {
+                            orig_path: &c.orig_path,
+                            data: &c.data,
+                            #[cfg(feature = "url_variables")]
+                            vars,
+                        })
+                    })
+                    .collect(),
+            );
+
+            let mut indices = vec![0_usize; matches.len()];
+            let total: usize = matches.iter().map(Vec::len).sum();
+            #[cfg(feature = "url_variables")]
+            let mut matched = MatchedRequest {
+                request: parsed_request.clone(),
+                vars: HashMap::new(),
+                state: &self.state,
+                extensions: Extensions::new(),
+            };
+            #[cfg(not(feature = "url_variables"))]
+            let matched = MatchedRequest {
+                request: parsed_request.clone(),
+                state: &self.state,
+                extensions: Extensions::new(),
+            };
+            // merge the closures in the order they were added, the
+            // middlewares are the last vector
+            let mut ordered = Vec::with_capacity(total);
+            for _ in 0..total {
+                let mut minind = 0;
+                let mut minval = usize::MAX;
+                for (n, (v, i)) in matches.iter().zip(indices.iter()).enumerate() {
+                    if *i < v.len() && v[*i].index < minval {
+                        minval = v[*i].index;
+                        minind = n;
+                    }
                 }
+                ordered.push((minind, &matches[minind][indices[minind]]));
+                indices[minind] += 1;
             }
-            #[cfg(feature = "url_variables")]
-            {
-                matched.vars = matches[minind][indices[minind]].vars.clone();
+            if self.match_order == MatchOrder::Specificity {
+                // the routes swap places among themselves, the
+                // middlewares stay where they are
+                let slots: Vec<usize> = (0..ordered.len())
+                    .filter(|n| ordered[*n].0 != matches.len() - 1)
+                    .collect();
+                let mut routes: Vec<_> = slots.iter().map(|n| ordered[*n]).collect();
+                routes.sort_by(|a, b| a.1.orig_path.specificity().cmp(b.1.orig_path.specificity()));
+                for (slot, route) in slots.into_iter().zip(routes.into_iter()) {
+                    ordered[slot] = route;
+                }
             }
-            let flow = (matches[minind][indices[minind]].closure)(&matched, &mut res);
-            indices[minind] += 1;
-            if !flow.should_continue() {
-                break;
+            for (_, matched_path) in ordered {
+                #[cfg(feature = "url_variables")]
+                {
+                    matched.vars = matched_path.vars.clone();
+                }
+                match CatchUnwind(matched_path.closure.call(&matched, res)).await {
+                    Ok(Ok(flow)) if flow.should_continue() => (),
+                    Ok(Ok(_)) => break,
+                    Ok(Err(err)) => {
+                        self.handle_error(&err, &matched, res);
+                        return;
+                    }
+                    Err(payload) => {
+                        self.handle_panic(payload, matched_path.orig_path, res);
+                        return;
+                    }
+                }
+            }
+            // the path exists but not for this method
+            if !routed && !res.has_body() {
+                let allowed = self.allowed_methods(&req.path);
+                if allowed.is_empty() {
+                    return;
+                }
+                if req.method == RequestMethod::Options {
+                    res.set("Allow", &allowed.join(", ")).send("");
+                } else {
+                    // the server sends the error page for the status
+                    res.status(StatusCode::MethodNotAllowed)
+                        .set("Allow", &allowed.join(", "));
+                }
             }
+        })
+    }
+    // Runs the error handlers scoped to the path of the request
+    // until one stops, the server sends the 500 error page otherwise
+    fn handle_error(&self, err: &RouteError, req: &MatchedRequest, res: &mut Response) {
+        let path = &req.request_line.path;
+        for handler in self.error_handlers.iter() {
+            if handler.orig_path.check_matches_prefix(path).is_none() {
+                continue;
+            }
+            let flow = panic::catch_unwind(AssertUnwindSafe(|| handler(err, req, res)));
+            match flow {
+                Ok(flow) if flow.should_continue() => (),
+                Ok(_) => return,
+                Err(payload) => return self.handle_panic(payload, &handler.orig_path, res),
+            }
+        }
+        *res = Response::new_empty();
+        res.status(StatusCode::InternalServerError);
+    }
+    // Reports the panic to the hook, whatever the closure put in
+    // the response is dropped and the server sends the 500 error page
+    fn handle_panic(&self, payload: Box<dyn Any + Send>, path: &PathBuf, res: &mut Response) {
+        if let Some(hook) = &self.panic_hook {
+            hook(&RoutePanic {
+                payload,
+                pattern: path.pattern(),
+            });
         }
+        *res = Response::new_empty();
+        res.status(StatusCode::InternalServerError);
+    }
+    // Tells if there are closures for the method on the path
+    fn has_route(&self, method: &RequestMethod, path: &PathBuf) -> bool {
+        self.paths
+            .get(method)
+            .map_or(false, |node| !node.get(path).is_empty())
+    }
+    // The methods which have closures on the path, OPTIONS is
+    // always allowed on paths that exist as it's answered
+    // automatically and so is HEAD when GET is
+    fn allowed_methods(&self, path: &PathBuf) -> Vec<&str> {
+        let mut allowed: Vec<&str> = self
+            .paths
+            .iter()
+            .filter(|(method, node)| {
+                method.is_some() && **method != RequestMethod::All && !node.get(path).is_empty()
+            })
+            .map(|(method, _)| method.as_str())
+            .collect();
+        if !allowed.is_empty() {
+            allowed.push(RequestMethod::Options.as_str());
+        }
+        if allowed.contains(&RequestMethod::Get.as_str()) {
+            allowed.push(RequestMethod::Head.as_str());
+        }
+        allowed.sort_unstable();
+        allowed.dedup();
+        allowed
     }
 }
 /// The route macro makes it easy to pass anonymous
@@ -350,7 +1100,172 @@ impl Router {
 macro_rules! route {
     ( | $req : ident, $res : ident | $body : expr ) => {{
         #[allow(unused_variables)]
-        Box::new(move |$req, $res| $body)
+        let closure: $crate::router::Closure = Box::new(move |$req, $res| $body);
+        closure
+    }};
+}
+
+/// The route_async macro is the async version of the
+/// [`route!()`](macro.route.html) macro, the body is an async
+/// block so you can `.await` inside of it. The closures on the
+/// same url run in order, one after the other finishes.
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+///
+/// async fn fetch_name() -> &'static str {
+///     "World"
+/// }
+///
+/// let mut app = Octane::new();
+/// app.get(
+///     "/",
+///     route_async!(
+///         |req, res| {
+///             res.send(format!("Hello, {}", fetch_name().await));
+///             Flow::Stop
+///         }
+///     ),
+/// );
+/// ```
+///
+/// The future can outlive the call to the closure, so values
+/// captured from the environment which aren't `Copy` have to be
+/// listed in brackets before the closure, they get cloned each
+/// time the closure is called
+///
+/// ```
+/// use octane::prelude::*;
+/// use std::sync::Arc;
+///
+/// let mut app = Octane::new();
+/// let name = Arc::new(String::from("World"));
+/// app.get(
+///     "/",
+///     route_async!(
+///         [name] |req, res| {
+///             res.send(format!("Hello, {}", name));
+///             Flow::Stop
+///         }
+///     ),
+/// );
+/// ```
+#[macro_export]
+macro_rules! route_async {
+    ( | $req : ident, $res : ident | $body : expr ) => {
+        $crate::route_async!([] | $req, $res | $body)
+    };
+    ( [ $( $capture : ident ),* ] | $req : ident, $res : ident | $body : expr ) => {{
+        #[allow(unused_variables)]
+        let closure: $crate::router::AsyncClosure = Box::new(move |$req, $res| {
+            $( let $capture = $capture.clone(); )*
+            Box::pin(async move { $body })
+        });
+        closure
+    }};
+}
+
+/// The route_try macro is the fallible version of the
+/// [`route!()`](macro.route.html) macro, the body returns a
+/// `Result<Flow, RouteError>` so errors can be propagated with
+/// `?`. An error skips the remaining closures and is handed to
+/// the error handlers added with `on_error()`.
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+///
+/// let mut app = Octane::new();
+/// app.get(
+///     "/users/:id",
+///     route_try!(
+///         |req, res| {
+///             let id: u64 = req.param("id")?;
+///             res.send(format!("User {}", id));
+///             Ok(Flow::Stop)
+///         }
+///     ),
+/// );
+/// ```
+#[macro_export]
+macro_rules! route_try {
+    ( | $req : ident, $res : ident | $body : expr ) => {{
+        #[allow(unused_variables)]
+        let closure: $crate::router::TryClosure = Box::new(move |$req, $res| $body);
+        closure
+    }};
+}
+
+/// The route_try_async macro is the async version of the
+/// [`route_try!()`](macro.route_try.html) macro, values captured
+/// from the environment are listed in brackets just like with
+/// [`route_async!()`](macro.route_async.html)
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+///
+/// async fn load_name(id: u64) -> Result<String, std::fmt::Error> {
+///     Ok(format!("User {}", id))
+/// }
+///
+/// let mut app = Octane::new();
+/// app.get(
+///     "/users/:id",
+///     route_try_async!(
+///         |req, res| {
+///             let id: u64 = req.param("id")?;
+///             res.send(load_name(id).await?);
+///             Ok(Flow::Stop)
+///         }
+///     ),
+/// );
+/// ```
+#[macro_export]
+macro_rules! route_try_async {
+    ( | $req : ident, $res : ident | $body : expr ) => {
+        $crate::route_try_async!([] | $req, $res | $body)
+    };
+    ( [ $( $capture : ident ),* ] | $req : ident, $res : ident | $body : expr ) => {{
+        #[allow(unused_variables)]
+        let closure: $crate::router::AsyncTryClosure = Box::new(move |$req, $res| {
+            $( let $capture = $capture.clone(); )*
+            Box::pin(async move { $body })
+        });
+        closure
+    }};
+}
+
+/// The route_error macro makes an error handler which can be
+/// given to `on_error()`, the closure gets the error along with
+/// the request and the response. Returning `Flow::Next` hands
+/// the error over to the next error handler.
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+/// use octane::responder::StatusCode;
+///
+/// let mut app = Octane::new();
+/// app.on_error(route_error!(
+///     |err, req, res| {
+///         res.status(StatusCode::InternalServerError)
+///             .send(format!("Something went wrong: {}", err));
+///         Flow::Stop
+///     }
+/// ));
+/// ```
+#[macro_export]
+macro_rules! route_error {
+    ( | $err : ident, $req : ident, $res : ident | $body : expr ) => {{
+        #[allow(unused_variables)]
+        let closure: $crate::router::ErrorClosure = Box::new(move |$err, $req, $res| $body);
+        closure
     }};
 }
 
@@ -412,9 +1327,11 @@ macro_rules! route_stop {
 
 default!(Router);
 
-#[macro_use]
 macro_rules! inject_method {
     ( $instance: expr, $path: expr, $closure: expr, $method: expr ) => {
+        inject_method!($instance, $path, $closure, $method, None);
+    };
+    ( $instance: expr, $path: expr, $closure: expr, $method: expr, $name: expr ) => {
         use crate::middlewares::Closures;
         use crate::path::{PathBuf, PathNode};
         $instance
@@ -422,10 +1339,11 @@ macro_rules! inject_method {
             .entry($method)
             .or_insert(PathNode::new())
             .insert(
-                PathBuf::parse($path)?,
+                PathBuf::parse_route($path)?,
                 Closures {
-                    closure: $closure,
+                    closure: $closure.into(),
                     index: $instance.route_counter,
+                    name: $name,
                 },
             );
         $instance.route_counter += 1;
@@ -433,39 +1351,74 @@ macro_rules! inject_method {
 }
 
 impl Route for Router {
-    fn head(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn head(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::Head);
         Ok(())
     }
-    fn put(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn put(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::Put);
         Ok(())
     }
-    fn get(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn get(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::Get);
         Ok(())
     }
-    fn delete(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn delete(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::Delete);
         Ok(())
     }
-    fn post(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn post(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::Post);
         Ok(())
     }
-    fn patch(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn patch(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::Patch);
         Ok(())
     }
-    fn add(&mut self, closure: Closure) -> RouterResult {
-        self.middlewares.push(Closures {
-            closure,
-            index: self.route_counter,
+    fn options(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
+        inject_method!(self, path, closure, RequestMethod::Options);
+        Ok(())
+    }
+    fn trace(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
+        inject_method!(self, path, closure, RequestMethod::Trace);
+        Ok(())
+    }
+    fn connect(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
+        inject_method!(self, path, closure, RequestMethod::Connect);
+        Ok(())
+    }
+    fn route(
+        &mut self,
+        method: RequestMethod,
+        path: &str,
+        closure: impl Into<Handler>,
+    ) -> RouterResult {
+        inject_method!(self, path, closure, method);
+        Ok(())
+    }
+    fn route_named(
+        &mut self,
+        name: &str,
+        method: RequestMethod,
+        path: &str,
+        closure: impl Into<Handler>,
+    ) -> RouterResult {
+        inject_method!(self, path, closure, method, Some(name.to_owned()));
+        Ok(())
+    }
+    fn add(&mut self, closure: impl Into<Handler>) -> RouterResult {
+        self.middlewares.push(PathData {
+            orig_path: PathBuf::new(),
+            data: Closures {
+                closure: closure.into(),
+                index: self.route_counter,
+                name: None,
+            },
         });
         self.route_counter += 1;
         Ok(())
     }
-    fn add_route(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn add_route(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         inject_method!(self, path, closure, RequestMethod::All);
         Ok(())
     }
@@ -475,6 +1428,24 @@ impl Route for Router {
 mod test {
     use super::*;
     use crate::path::PathBuf;
+    #[cfg(feature = "url_variables")]
+    use crate::request::ParamError;
+    use crate::request::{parse_without_body, Request};
+    use std::sync::{Arc, Mutex};
+
+    // Run the router on a request without a body, returning the
+    // status and headers of the response
+    async fn run_request(
+        router: &Router,
+        request_line: &str,
+    ) -> (StatusCode, HashMap<String, String>) {
+        let (request_line, headers) =
+            parse_without_body(&format!("{}\r\nHost: localhost", request_line)).unwrap();
+        let request = Request::parse(request_line, &headers, b"").unwrap();
+        let mut res = Response::new_empty();
+        router.run(request, &mut res).await;
+        (res.status_code, res.headers)
+    }
 
     #[test]
     pub fn router_test() {
@@ -489,6 +1460,26 @@ mod test {
             .unwrap();
         // middleware with path
         assert_eq!(5, router.paths.len());
+        router
+            .options("/", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        router
+            .trace("/", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        router
+            .connect("/", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        router
+            .route(
+                RequestMethod::parse("PROPFIND"),
+                "/",
+                route!(|req, res| { Flow::Next }),
+            )
+            .unwrap();
+        assert_eq!(9, router.paths.len());
+        assert!(router.implements(&RequestMethod::parse("PROPFIND")));
+        assert!(!router.implements(&RequestMethod::parse("MKCOL")));
+        assert!(!router.implements(&RequestMethod::None));
         // middleware without paths
         assert_eq!(1, router.middlewares.len());
     }
@@ -581,4 +1572,504 @@ mod test {
                 .len()
         );
     }
+
+    #[crate::test]
+    async fn router_run_async_test() {
+        // Async and sync closures should run in the order they were added.
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new();
+        let log = Arc::clone(&order);
+        router
+            .add(route!(|req, res| {
+                log.lock().unwrap().push(0);
+                Flow::Next
+            }))
+            .unwrap();
+        let log = Arc::clone(&order);
+        router
+            .get(
+                "/",
+                route_async!(
+                    [log] | req,
+                    res | {
+                        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                        log.lock().unwrap().push(1);
+                        Flow::Next
+                    }
+                ),
+            )
+            .unwrap();
+        let log = Arc::clone(&order);
+        router
+            .add(route_async!(
+                [log] | req,
+                res | {
+                    log.lock().unwrap().push(2);
+                    Flow::Stop
+                }
+            ))
+            .unwrap();
+        let log = Arc::clone(&order);
+        router
+            .get(
+                "/",
+                route!(|req, res| {
+                    log.lock().unwrap().push(3);
+                    Flow::Next
+                }),
+            )
+            .unwrap();
+        run_request(&router, "GET / HTTP/1.1").await;
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[crate::test]
+    async fn router_state_test() {
+        // Managed state should be visible to the closures after appending.
+        let seen = Arc::new(Mutex::new(None));
+        let mut router = Router::new();
+        router.manage(String::from("app"));
+        let mut second_router = Router::new();
+        second_router.manage(String::from("sub"));
+        second_router.manage(7_u32);
+        let log = Arc::clone(&seen);
+        second_router
+            .get(
+                "/",
+                route!(|req, res| {
+                    let name = req.state::<String>().unwrap().clone();
+                    *log.lock().unwrap() = Some((name, *req.state::<u32>().unwrap()));
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        router.append(second_router);
+        run_request(&router, "GET / HTTP/1.1").await;
+        assert_eq!(*seen.lock().unwrap(), Some(("app".to_owned(), 7)));
+    }
+
+    #[crate::test]
+    async fn router_extensions_test() {
+        // Values inserted by a middleware should reach the route.
+        let seen = Arc::new(Mutex::new(None));
+        let mut router = Router::new();
+        router
+            .add(route!(|req, res| {
+                req.extensions().insert(String::from("user"));
+                Flow::Next
+            }))
+            .unwrap();
+        let log = Arc::clone(&seen);
+        router
+            .get(
+                "/",
+                route_async!(
+                    [log] | req,
+                    res | {
+                        let user = req.extensions().get::<String>().map(|v| (*v).clone());
+                        *log.lock().unwrap() = user;
+                        Flow::Stop
+                    }
+                ),
+            )
+            .unwrap();
+        run_request(&router, "GET / HTTP/1.1").await;
+        assert_eq!(*seen.lock().unwrap(), Some("user".to_owned()));
+    }
+
+    #[crate::test]
+    #[cfg(feature = "url_variables")]
+    async fn router_mount_test() {
+        // Mounted routes and middlewares should only run under the prefix.
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut users = Router::new();
+        let log = Arc::clone(&seen);
+        users
+            .add(route!(|req, res| {
+                log.lock().unwrap().push(format!("mw {}", req.vars["id"]));
+                Flow::Next
+            }))
+            .unwrap();
+        let log = Arc::clone(&seen);
+        users
+            .get(
+                "/posts/:post",
+                route!(|req, res| {
+                    let (id, post) = (req.vars["id"], req.vars["post"]);
+                    log.lock().unwrap().push(format!("posts {} {}", id, post));
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        let mut router = Router::new();
+        let log = Arc::clone(&seen);
+        router
+            .get(
+                "/posts/:post",
+                route!(|req, res| {
+                    log.lock()
+                        .unwrap()
+                        .push(format!("root {}", req.vars["post"]));
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        router.mount("/users/:id", users).unwrap();
+
+        run_request(&router, "GET /users/5/posts/2 HTTP/1.1").await;
+        run_request(&router, "GET /posts/3 HTTP/1.1").await;
+        run_request(&router, "GET /users HTTP/1.1").await;
+        assert_eq!(*seen.lock().unwrap(), vec!["mw 5", "posts 5 2", "root 3"]);
+    }
+
+    #[crate::test]
+    async fn router_custom_method_test() {
+        // Closures for extension methods should only run on that method.
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new();
+        let log = Arc::clone(&seen);
+        router
+            .route(
+                RequestMethod::Custom("PROPFIND".to_owned()),
+                "/dav",
+                route!(|req, res| {
+                    let method = req.request_line.method.as_str().to_owned();
+                    log.lock().unwrap().push(method);
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        run_request(&router, "PROPFIND /dav HTTP/1.1").await;
+        run_request(&router, "MKCOL /dav HTTP/1.1").await;
+        run_request(&router, "GET /dav HTTP/1.1").await;
+        assert_eq!(*seen.lock().unwrap(), vec!["PROPFIND"]);
+    }
+
+    #[crate::test]
+    async fn router_method_not_allowed_test() {
+        // Paths existing under other methods should answer 405 and OPTIONS.
+        let mut router = Router::new();
+        router
+            .get("/users/5", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        router
+            .delete("/users/5", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        router
+            .options("/custom", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        let (status, headers) = run_request(&router, "POST /users/5 HTTP/1.1").await;
+        assert_eq!(status, StatusCode::MethodNotAllowed);
+        assert_eq!(headers["Allow"], "DELETE, GET, HEAD, OPTIONS");
+        let (status, headers) = run_request(&router, "OPTIONS /users/5 HTTP/1.1").await;
+        assert_eq!(status, StatusCode::Ok);
+        assert_eq!(headers["Allow"], "DELETE, GET, HEAD, OPTIONS");
+        assert_eq!(headers["Content-Length"], "0");
+        // Routes for the method itself and unknown paths are left alone.
+        let (_, headers) = run_request(&router, "OPTIONS /custom HTTP/1.1").await;
+        assert!(!headers.contains_key("Allow"));
+        let (status, _) = run_request(&router, "POST /posts HTTP/1.1").await;
+        assert_eq!(status, StatusCode::Ok);
+    }
+
+    #[crate::test]
+    async fn router_head_test() {
+        // HEAD should fall back to GET unless it has its own closures.
+        let mut router = Router::new();
+        router
+            .get(
+                "/",
+                route!(|req, res| {
+                    res.send("Hello, World");
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        router
+            .get(
+                "/own",
+                route!(|req, res| {
+                    res.send("GET");
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        router
+            .head(
+                "/own",
+                route!(|req, res| {
+                    res.send("HEAD");
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        let (status, headers) = run_request(&router, "HEAD / HTTP/1.1").await;
+        assert_eq!(status, StatusCode::Ok);
+        assert_eq!(headers["Content-Length"], "12");
+        let (_, headers) = run_request(&router, "HEAD /own HTTP/1.1").await;
+        assert_eq!(headers["Content-Length"], "4");
+    }
+
+    #[crate::test]
+    #[cfg(feature = "url_variables")]
+    async fn router_param_test() {
+        // Typed variables should only reach the matching closures.
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new();
+        let log = Arc::clone(&seen);
+        router
+            .get(
+                "/users/:id<u64>",
+                route!(|req, res| {
+                    let id: u64 = req.param("id").unwrap();
+                    log.lock().unwrap().push(id);
+                    assert_eq!(req.param::<u64>("name"), Err(ParamError::Missing));
+                    assert!(matches!(req.param::<u8>("id"), Err(ParamError::Invalid(_))));
+                    Flow::Stop
+                }),
+            )
+            .unwrap();
+        assert!(router
+            .get("/posts/:id<u65>", route!(|req, res| { Flow::Stop }))
+            .is_err());
+        run_request(&router, "GET /users/300 HTTP/1.1").await;
+        run_request(&router, "GET /users/abc HTTP/1.1").await;
+        assert_eq!(*seen.lock().unwrap(), vec![300]);
+    }
+
+    #[crate::test]
+    async fn router_specificity_test() {
+        // Specific routes should run first, middlewares should keep their place.
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new();
+        let log = Arc::clone(&seen);
+        router
+            .add(route!(|req, res| {
+                log.lock().unwrap().push("mw");
+                Flow::Next
+            }))
+            .unwrap();
+        for (path, name) in [
+            ("/users/*rest", "wildcard"),
+            ("/users/:id", "param"),
+            ("/users/me", "static"),
+            ("/users/:name", "param2"),
+        ]
+        .iter()
+        {
+            let log = Arc::clone(&seen);
+            router
+                .get(
+                    path,
+                    route!(|req, res| {
+                        log.lock().unwrap().push(name);
+                        Flow::Next
+                    }),
+                )
+                .unwrap();
+        }
+        run_request(&router, "GET /users/me HTTP/1.1").await;
+        #[cfg(feature = "url_variables")]
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["mw", "wildcard", "param", "static", "param2"]
+        );
+        seen.lock().unwrap().clear();
+        router.match_order(MatchOrder::Specificity);
+        run_request(&router, "GET /users/me HTTP/1.1").await;
+        #[cfg(feature = "url_variables")]
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["mw", "static", "param", "param2", "wildcard"]
+        );
+        #[cfg(not(feature = "url_variables"))]
+        assert_eq!(*seen.lock().unwrap(), vec!["mw", "static"]);
+    }
+
+    #[test]
+    fn router_url_for_test() {
+        // Named routes should expand to their url, including mount prefixes.
+        let mut files = Router::new();
+        files
+            .route_named(
+                "file",
+                RequestMethod::Get,
+                "/*path",
+                route!(|req, res| { Flow::Next }),
+            )
+            .unwrap();
+        let mut router = Router::new();
+        router
+            .route_named(
+                "home",
+                RequestMethod::Get,
+                "/",
+                route!(|req, res| { Flow::Next }),
+            )
+            .unwrap();
+        router.mount("/users/:id<u64>/files", files).unwrap();
+        assert_eq!(router.url_for("home", &[]), Ok("/".to_owned()));
+        assert_eq!(
+            router.url_for("file", &[("id", "5"), ("path", "a b/c.txt")]),
+            Ok("/users/5/files/a%20b/c.txt".to_owned())
+        );
+        assert_eq!(
+            router.url_for("file", &[("id", "x"), ("path", "c.txt")]),
+            Err(UrlError::InvalidParam("id".to_owned()))
+        );
+        assert_eq!(
+            router.url_for("file", &[("id", "5")]),
+            Err(UrlError::MissingParam("path".to_owned()))
+        );
+        assert_eq!(
+            router.url_for("nope", &[]),
+            Err(UrlError::UnknownRoute("nope".to_owned()))
+        );
+    }
+
+    #[test]
+    fn router_routes_test() {
+        // Routes and middlewares should be listed in the order they were added.
+        let mut api = Router::new();
+        api.add(route!(|req, res| { Flow::Next })).unwrap();
+        api.post("/users", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        let mut router = Router::new();
+        router.get("/", route!(|req, res| { Flow::Next })).unwrap();
+        router
+            .add_route("/health", route!(|req, res| { Flow::Next }))
+            .unwrap();
+        router.mount("/api/:version", api).unwrap();
+        let routes: Vec<_> = router
+            .routes()
+            .map(|(method, pattern, index, kind)| (method.clone(), pattern, index, kind))
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                (RequestMethod::Get, "/".to_owned(), 0, RouteKind::Route),
+                (
+                    RequestMethod::All,
+                    "/health".to_owned(),
+                    1,
+                    RouteKind::Route
+                ),
+                (
+                    RequestMethod::All,
+                    "/api/:version".to_owned(),
+                    2,
+                    RouteKind::Middleware
+                ),
+                (
+                    RequestMethod::Post,
+                    "/api/:version/users".to_owned(),
+                    3,
+                    RouteKind::Route
+                ),
+            ]
+        );
+    }
+
+    #[crate::test]
+    async fn router_error_test() {
+        // Errors should skip the remaining closures and reach the error handlers.
+        let ran = Arc::new(Mutex::new(false));
+        let mut router = Router::new();
+        router
+            .get("/fail", route_try!(|req, res| { Err("broken".into()) }))
+            .unwrap();
+        let log = Arc::clone(&ran);
+        router
+            .add(route!(|req, res| {
+                *log.lock().unwrap() = true;
+                Flow::Next
+            }))
+            .unwrap();
+        // without handlers the default error page is sent
+        assert_eq!(
+            run_request(&router, "GET /fail HTTP/1.1").await.0,
+            StatusCode::InternalServerError
+        );
+        assert!(!*ran.lock().unwrap());
+        let mut api = Router::new();
+        api.get(
+            "/fail",
+            route_try_async!(|req, res| {
+                let id: u64 = "abc".parse()?;
+                res.send(id.to_string());
+                Ok(Flow::Stop)
+            }),
+        )
+        .unwrap();
+        api.on_error(route_error!(|err, req, res| {
+            res.status(StatusCode::BadRequest).set("X-Handler", "api");
+            Flow::Stop
+        }));
+        router.mount("/api", api).unwrap();
+        router.on_error(route_error!(|err, req, res| {
+            res.set("X-Error", &err.to_string());
+            Flow::Next
+        }));
+        router.on_error(route_error!(|err, req, res| {
+            res.status(StatusCode::ServiceUnavailable);
+            Flow::Stop
+        }));
+        let (status, headers) = run_request(&router, "GET /fail HTTP/1.1").await;
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert_eq!(headers.get("X-Error").unwrap(), "broken");
+        // the handlers of the mounted router are scoped to its prefix
+        let (status, headers) = run_request(&router, "GET /api/fail HTTP/1.1").await;
+        assert_eq!(status, StatusCode::BadRequest);
+        assert_eq!(headers.get("X-Handler").unwrap(), "api");
+        assert!(headers.get("X-Error").is_none());
+    }
+
+    #[crate::test]
+    async fn router_panic_test() {
+        // Panics should be reported to the hook and answered with a 500.
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let ran = Arc::new(Mutex::new(false));
+        let mut router = Router::new();
+        router
+            .get("/sync", route!(|req, res| { panic!("sync panic") }))
+            .unwrap();
+        router
+            .get(
+                "/async",
+                route_async!(|req, res| {
+                    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                    panic!("{} panic", "async");
+                }),
+            )
+            .unwrap();
+        let log = Arc::clone(&ran);
+        router
+            .add(route!(|req, res| {
+                *log.lock().unwrap() = true;
+                Flow::Next
+            }))
+            .unwrap();
+        let log = Arc::clone(&reported);
+        router.on_panic(move |panic| {
+            log.lock()
+                .unwrap()
+                .push((panic.pattern.clone(), panic.message().unwrap().to_owned()));
+        });
+        assert_eq!(
+            run_request(&router, "GET /sync HTTP/1.1").await.0,
+            StatusCode::InternalServerError
+        );
+        assert_eq!(
+            run_request(&router, "GET /async HTTP/1.1").await.0,
+            StatusCode::InternalServerError
+        );
+        assert!(!*ran.lock().unwrap());
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![
+                ("/sync".to_owned(), "sync panic".to_owned()),
+                ("/async".to_owned(), "async panic".to_owned()),
+            ]
+        );
+    }
 }
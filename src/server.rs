@@ -1,23 +1,33 @@
-use crate::config::{Config, OctaneConfig, Ssl};
+use crate::config::{Config, ErrorPage, Limits, OctaneConfig, SocketOptions, Ssl, Timeouts};
 use crate::constants::*;
 use crate::error::Error;
-use crate::http::Http;
-use crate::middlewares::Closures;
-use crate::request::{parse_without_body, Headers, Request, RequestLine};
+use crate::http::chunked::{is_chunked, ChunkedDecoder, DecodeState};
+use crate::http::{Http, KeepAliveState};
+use crate::request::{parse_without_body, Headers, HttpVersion, Request, RequestMethod};
 use crate::responder::{BoxReader, Response, StatusCode};
 use crate::route;
-use crate::router::{Closure, Flow, Route, Router, RouterResult};
-use crate::server_builder::ServerBuilder;
-use crate::tls::AsMutStream;
-use crate::util::find_in_slice;
+use crate::router::{
+    BoxFuture, Closure, ErrorClosure, Flow, Handler, MatchOrder, Route, RouteKind, RoutePanic,
+    Router, RouterResult, UrlError,
+};
+#[cfg(unix)]
+use crate::server_builder::UnixServerBuilder;
+use crate::server_builder::{ServerBuilder, Shutdown, ShutdownHandle, ToListenAddrs};
+use crate::util::{find_in_slice, first, until};
 use crate::{declare_error, default, route_next};
 use std::error::Error as StdError;
+use std::future::{pending, Future};
 use std::marker::Unpin;
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::str;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{copy, split, AsyncWriteExt};
+use tokio::io::{copy, empty, split, AsyncWriteExt};
 use tokio::prelude::*;
+use tokio::signal::ctrl_c;
+use tokio::time::{timeout, Instant};
 
 /// The Octane server
 ///
@@ -50,6 +60,14 @@ pub struct Octane {
     router: Router,
 }
 
+// What the server listens on
+enum Bind {
+    Tcp(Vec<SocketAddr>),
+    Listener(TcpListener),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
 impl Octane {
     /// Creates a new server instance with empty config and empty router
     pub fn new() -> Self {
@@ -104,6 +122,175 @@ impl Octane {
     pub fn with_router(&mut self, router: Router) {
         self.router.append(router);
     }
+    /// Mounts the router under a path prefix, like `app.use("/api", router)`
+    /// in express. The routes of the router are moved under the
+    /// prefix and its middlewares only run on the paths which
+    /// start with the prefix, so each part of the app can live in
+    /// its own self contained router
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// let mut api = Router::new();
+    /// api.add(route_next!(|req, res| {
+    ///     res.set("Content-Type", "application/json");
+    /// }));
+    /// // served on /api/users/:id
+    /// api.get("/users/:id", route!(|req, res| {
+    ///     res.send(format!("{{\"id\": \"{}\"}}", req.vars.get("id").unwrap()));
+    ///     Flow::Stop
+    /// }));
+    /// app.mount("/api", api).unwrap();
+    /// ```
+    pub fn mount(&mut self, prefix: &str, router: Router) -> RouterResult {
+        self.router.mount(prefix, router)
+    }
+    /// Stores a value in the application state, the closures
+    /// can borrow it with
+    /// [`req.state()`](request/struct.MatchedRequest.html#method.state).
+    /// This is the place for things like connection pools or
+    /// configuration which every route needs
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// struct Config {
+    ///     name: String,
+    /// }
+    ///
+    /// let mut app = Octane::new();
+    /// app.manage(Config { name: "Octane".to_owned() });
+    /// app.get(
+    ///     "/",
+    ///     route!(|req, res| {
+    ///         let config = req.state::<Config>().unwrap();
+    ///         res.send(format!("Hello from {}", config.name));
+    ///         Flow::Stop
+    ///     }),
+    /// );
+    /// ```
+    ///
+    /// Only one value of each type is kept, managing a value of
+    /// the same type again replaces the previous one. Values
+    /// managed by routers given to `with_router` are only added
+    /// if the app doesn't already have a value of that type.
+    pub fn manage<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.router.manage(value);
+    }
+    /// Adds an error handler which runs when a closure made with
+    /// [`route_try!()`](macro.route_try.html) returns an error, it
+    /// can be used to send the same error response from every
+    /// route. The error handlers run in the order they were added
+    /// until one returns `Flow::Stop`, a 500 error page is sent
+    /// if none does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::responder::StatusCode;
+    ///
+    /// let mut app = Octane::new();
+    /// app.get(
+    ///     "/users/:id",
+    ///     route_try!(|req, res| {
+    ///         let id: u64 = req.param("id")?;
+    ///         res.send(format!("User {}", id));
+    ///         Ok(Flow::Stop)
+    ///     }),
+    /// );
+    /// app.on_error(route_error!(|err, req, res| {
+    ///     let mut envelope = std::collections::HashMap::new();
+    ///     envelope.insert("error".to_owned(), err.to_string());
+    ///     res.status(StatusCode::BadRequest).json(envelope);
+    ///     Flow::Stop
+    /// }));
+    /// ```
+    pub fn on_error(&mut self, handler: ErrorClosure) {
+        self.router.on_error(handler);
+    }
+    /// Sets the hook which is told about closures that panic
+    /// while handling a request, with the panic payload and the
+    /// pattern of the route. A panicking closure gets the client
+    /// a 500 error page instead of a dropped connection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.on_panic(|panic| {
+    ///     eprintln!(
+    ///         "{} panicked: {}",
+    ///         panic.pattern,
+    ///         panic.message().unwrap_or("unknown payload")
+    ///     );
+    /// });
+    /// ```
+    pub fn on_panic<F>(&mut self, hook: F)
+    where
+        F: Fn(&RoutePanic) + Send + Sync + 'static,
+    {
+        self.router.on_panic(hook);
+    }
+    /// Builds the url of the route with the given name, the values
+    /// of the url variables are percent encoded. Errors if there's
+    /// no route with the name or a value for a url variable is
+    /// missing or doesn't fit the constraint on it
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    /// use octane::request::RequestMethod;
+    ///
+    /// let mut app = Octane::new();
+    /// app.route_named(
+    ///     "user_detail",
+    ///     RequestMethod::Get,
+    ///     "/users/:id",
+    ///     route!(|req, res| {
+    ///         res.send("A user");
+    ///         Flow::Stop
+    ///     }),
+    /// );
+    /// let url = app.url_for("user_detail", &[("id", "5")]).unwrap();
+    /// assert_eq!(url, "/users/5");
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        self.router.url_for(name, params)
+    }
+    /// Lists every route and middleware of the app as
+    /// `(method, pattern, index, kind)` in the order they were
+    /// added, see [`Router::routes()`](router/struct.Router.html#method.routes)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use octane::prelude::*;
+    ///
+    /// let mut app = Octane::new();
+    /// app.get("/", route!(|req, res| { Flow::Stop }));
+    /// app.post("/users", route!(|req, res| { Flow::Stop }));
+    /// for (method, pattern, index, kind) in app.routes() {
+    ///     println!("{} {:?} {} ({:?})", index, method, pattern, kind);
+    /// }
+    /// ```
+    pub fn routes(&self) -> impl Iterator<Item = (&RequestMethod, String, usize, RouteKind)> {
+        self.router.routes()
+    }
+    /// Sets the order in which the closures matching a request
+    /// run, by default they run in the order they were added.
+    /// See [`MatchOrder`](router/enum.MatchOrder.html)
+    pub fn match_order(&mut self, order: MatchOrder) {
+        self.router.match_order(order);
+    }
     /// Returns a closure which can be used with the add or add_route method
     /// to serve a static directory.
     ///
@@ -131,9 +318,12 @@ impl Octane {
             };
         })
     }
-    /// Start listening on the port specified, the listen
-    /// function also starts the Ssl server if the features
-    /// are enabled and the key/certs are provided
+    /// Start listening on the addresses specified, which can be
+    /// a port, anything implementing `ToSocketAddrs` or a list
+    /// of them (see [`ToListenAddrs`](trait.ToListenAddrs.html)).
+    /// The listen function also starts the Ssl server on the
+    /// same IPs if the features are enabled and the key/certs
+    /// are provided
     ///
     /// # Example
     ///
@@ -149,122 +339,569 @@ impl Octane {
     ///         .expect("Cannot establish connection");
     /// }
     /// ```
-    pub async fn listen<F>(self, port: u16, exec: F) -> Result<(), Box<dyn StdError>>
+    pub async fn listen<A, F>(self, addr: A, exec: F) -> Result<(), Box<dyn StdError>>
+    where
+        A: ToListenAddrs,
+        F: FnOnce(),
+    {
+        self.run(Bind::Tcp(addr.to_listen_addrs()?), exec, pending::<()>())
+            .await
+    }
+    /// Same as [`listen()`](#method.listen) but the server shuts
+    /// down once the signal future finishes. New connections
+    /// stop being accepted on both the plain and the Ssl
+    /// listeners, idle connections are closed and the requests
+    /// which are being handled are given until the shutdown
+    /// timeout (see [`Timeouts`](config/struct.Timeouts.html))
+    /// to finish before this returns. The connections still open
+    /// by then are dropped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let app = Octane::new();
+    ///     app.listen_with_shutdown(8080, Octane::shutdown_signal())
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    ///     println!("Server stopped");
+    /// }
+    /// ```
+    pub async fn listen_with_shutdown<A, S>(
+        self,
+        addr: A,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        A: ToListenAddrs,
+        S: Future,
+    {
+        self.run(Bind::Tcp(addr.to_listen_addrs()?), || {}, signal)
+            .await
+    }
+    /// Binds a listener to the first of the addresses which can
+    /// be bound with the options of
+    /// [`SocketOptions`](config/struct.SocketOptions.html), to be
+    /// served on with [`serve_on()`](#method.serve_on). Binding
+    /// to port 0 gets a free port from the OS and the address
+    /// actually bound can be read from the listener before
+    /// serving, which lets every test run its own server.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::Octane;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let app = Octane::new();
+    ///     let listener = app.bind("127.0.0.1:0").expect("Cannot bind");
+    ///     println!("Server started on {}", listener.local_addr().unwrap());
+    ///     app.serve_on(listener)
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    pub fn bind<A: ToListenAddrs>(&self, addr: A) -> std::io::Result<TcpListener> {
+        let mut error = None;
+        for addr in addr.to_listen_addrs()? {
+            match ServerBuilder::bind(addr, &self.settings.socket) {
+                Ok(listener) => return Ok(listener),
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address to listen on")
+        }))
+    }
+    /// Serves on a listener which is already bound, like one made
+    /// by [`bind()`](#method.bind) or handed over by a process
+    /// manager. The socket options of the config aren't applied
+    /// to it, the Ssl server is started on its IP like with
+    /// [`listen()`](#method.listen).
+    pub async fn serve_on(self, listener: TcpListener) -> Result<(), Box<dyn StdError>> {
+        self.run(Bind::Listener(listener), || {}, pending::<()>())
+            .await
+    }
+    /// Same as [`serve_on()`](#method.serve_on) but the server
+    /// shuts down once the signal future finishes, like with
+    /// [`listen_with_shutdown()`](#method.listen_with_shutdown)
+    pub async fn serve_on_with_shutdown<S>(
+        self,
+        listener: TcpListener,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        S: Future,
+    {
+        self.run(Bind::Listener(listener), || {}, signal).await
+    }
+    /// Start listening on a unix domain socket at the path
+    /// specified, which is how a proxy like nginx on the same
+    /// machine usually talks to the server. A socket file left
+    /// behind by a server which didn't exit cleanly is replaced
+    /// and the file is removed once the server stops. The Ssl
+    /// server isn't started, the permissions of the file are set
+    /// through [`SocketOptions`](config/struct.SocketOptions.html).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use octane::config::Config;
+    /// use octane::Octane;
+    ///
+    /// #[octane::main]
+    /// async fn main() {
+    ///     let mut app = Octane::new();
+    ///     app.socket().unix_mode(0o660);
+    ///     app.listen_unix("/run/octane.sock", || println!("Server started"))
+    ///         .await
+    ///         .expect("Cannot establish connection");
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub async fn listen_unix<P, F>(self, path: P, exec: F) -> Result<(), Box<dyn StdError>>
     where
+        P: AsRef<Path>,
         F: FnOnce(),
+    {
+        let path = path.as_ref().to_path_buf();
+        self.run(Bind::Unix(path), exec, pending::<()>()).await
+    }
+    /// Same as [`listen_unix()`](#method.listen_unix) but the
+    /// server shuts down once the signal future finishes, like
+    /// with [`listen_with_shutdown()`](#method.listen_with_shutdown)
+    #[cfg(unix)]
+    pub async fn listen_unix_with_shutdown<P, S>(
+        self,
+        path: P,
+        signal: S,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        P: AsRef<Path>,
+        S: Future,
+    {
+        let path = path.as_ref().to_path_buf();
+        self.run(Bind::Unix(path), || {}, signal).await
+    }
+    /// Returns a future which finishes once the process gets
+    /// SIGTERM or SIGINT (ctrl-c), it can be given to
+    /// [`listen_with_shutdown()`](#method.listen_with_shutdown).
+    /// Only ctrl-c is listened to on platforms other than unix.
+    pub async fn shutdown_signal() {
+        // a signal which can't be listened to never arrives
+        let interrupt = async {
+            if ctrl_c().await.is_err() {
+                pending::<()>().await;
+            }
+        };
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let terminate = async {
+                match signal(SignalKind::terminate()) {
+                    Ok(mut terminate) => {
+                        terminate.recv().await;
+                    }
+                    Err(_) => pending::<()>().await,
+                }
+            };
+            first(interrupt, terminate).await;
+        }
+        #[cfg(not(unix))]
+        interrupt.await;
+    }
+    // Runs the listeners until the signal finishes, then waits for
+    // the connections to be done
+    async fn run<F, S>(self, bind: Bind, exec: F, signal: S) -> Result<(), Box<dyn StdError>>
+    where
+        F: FnOnce(),
+        S: Future,
     {
         let server = Arc::new(self);
+        let (handle, shutdown) = ShutdownHandle::new();
         let mut _ssl = false;
 
         #[cfg(any(feature = "openSSL", feature = "rustls"))]
         {
             use crate::task;
             let clone = Arc::clone(&server);
+            let ssl_shutdown = shutdown.clone();
             _ssl = true;
+            // the ssl server listens on the same IPs on its own port
+            let addrs = match &bind {
+                Bind::Tcp(addrs) => addrs.clone(),
+                Bind::Listener(listener) => listener.local_addr().into_iter().collect(),
+                #[cfg(unix)]
+                Bind::Unix(_) => Vec::new(),
+            };
+            let mut ssl_addrs: Vec<SocketAddr> = Vec::new();
+            for addr in addrs.iter() {
+                let ssl_addr = SocketAddr::new(addr.ip(), server.settings.ssl.port);
+                if !ssl_addrs.contains(&ssl_addr) {
+                    ssl_addrs.push(ssl_addr);
+                }
+            }
 
-            async fn listen_ssl(server: Arc<Octane>) -> Result<(), Box<dyn StdError>> {
-                let server_builder = ServerBuilder::new(server.settings.ssl.port);
+            async fn listen_ssl(
+                server: Arc<Octane>,
+                addrs: Vec<SocketAddr>,
+                shutdown: Shutdown,
+            ) -> Result<(), Box<dyn StdError>> {
+                let server_builder = ServerBuilder::new(&addrs, &server.settings.socket);
                 server_builder?
                     .listen_ssl(
-                        |stream, server| async { Octane::serve(stream, server).await },
+                        |stream, server, shutdown| async move {
+                            Octane::serve(stream, server, shutdown).await
+                        },
                         server,
+                        shutdown,
                     )
                     .await?;
                 Ok(())
             }
 
-            task!({
-                if let Err(x) = listen_ssl(clone).await {
-                    println!("WARNING: {}", x);
-                }
-            });
+            if !ssl_addrs.is_empty() {
+                task!({
+                    if let Err(x) = listen_ssl(clone, ssl_addrs, ssl_shutdown).await {
+                        println!("WARNING: {}", x);
+                    }
+                });
+            }
         }
         exec();
-        let server_builder = ServerBuilder::new(port);
-        server_builder?
-            .listen(
-                move |stream, server| async move { Octane::serve(stream, server).await },
-                server,
-            )
-            .await?;
+        let timeout = server.settings.timeouts.shutdown;
+        let options = &server.settings.socket;
+        let tcp =
+            |server_builder: ServerBuilder| -> BoxFuture<'static, std::io::Result<()>> {
+                Box::pin(server_builder.listen(
+                    |stream, server, shutdown| async move {
+                        Octane::serve(stream, server, shutdown).await
+                    },
+                    Arc::clone(&server),
+                    shutdown.clone(),
+                ))
+            };
+        let listening =
+            match bind {
+                Bind::Tcp(addrs) => tcp(ServerBuilder::new(&addrs, options)?),
+                Bind::Listener(listener) => tcp(ServerBuilder::from_std(vec![listener])?),
+                #[cfg(unix)]
+                Bind::Unix(path) => Box::pin(UnixServerBuilder::new(&path, options)?.listen(
+                    |stream, server, shutdown| async move {
+                        Octane::serve(stream, server, shutdown).await
+                    },
+                    Arc::clone(&server),
+                    shutdown.clone(),
+                )),
+            };
+        let result = first(listening, async {
+            signal.await;
+            Ok(())
+        })
+        .await;
+        handle.shutdown(shutdown, timeout).await;
+        result?;
 
         Ok(())
     }
 
-    async fn serve<S>(stream_async: S, server: Arc<Octane>) -> Result<(), Box<dyn StdError>>
+    pub(crate) async fn serve<S>(
+        stream_async: S,
+        server: Arc<Octane>,
+        shutdown: Shutdown,
+    ) -> Result<(), Box<dyn StdError>>
     where
-        S: AsyncRead + AsyncWrite + Unpin + AsMutStream,
+        S: AsyncRead + AsyncWrite + Unpin,
     {
-        let (mut reader, writer) = split(stream_async);
+        let (mut reader, mut writer) = split(stream_async);
         let mut data = Vec::<u8>::new();
         let mut buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-        let body: &[u8];
-        let request_line: RequestLine;
-        let headers: Headers;
-        let body_remainder: &[u8];
+        // number of requests served on this connection and the time
+        // we wait for the next one, None if it is the first request
+        let mut served: u64 = 0;
+        let mut idle: Option<Duration> = None;
+        let timeouts = &server.settings.timeouts;
 
         loop {
-            let read = reader.read(&mut buf).await?;
-            if read == 0 {
-                declare_error!(writer, StatusCode::BadRequest);
-            }
-            let cur = &buf[..read];
-
-            data.extend_from_slice(cur);
-            if let Some(i) = find_in_slice(&data[..], b"\r\n\r\n") {
-                let first = &data[..i];
-                body_remainder = &data[i + 4..];
-                if let Ok(Some((rl, heads))) = str::from_utf8(first).map(parse_without_body) {
-                    request_line = rl;
-                    headers = heads;
-                    break;
-                } else {
-                    declare_error!(writer, StatusCode::BadRequest);
+            let mut head_deadline: Option<Instant> = None;
+            let head_end = loop {
+                if let Some(i) = find_in_slice(&data[..], b"\r\n\r\n") {
+                    break i;
+                }
+                if let Some(status) = server.check_head(&data, false) {
+                    declare_error!(&mut writer, status, &server.settings);
+                }
+                let read = match idle {
+                    // an idle keep alive connection is closed silently
+                    Some(duration) if data.is_empty() => {
+                        let reading = async { timeout(duration, reader.read(&mut buf)).await.ok() };
+                        let closing = async {
+                            shutdown.wait().await;
+                            None
+                        };
+                        match first(reading, closing).await {
+                            Some(read) => read?,
+                            None => return Ok(()),
+                        }
+                    }
+                    _ => {
+                        // the deadline starts with the first read of the request
+                        if head_deadline.is_none() {
+                            head_deadline = timeouts.header_read.map(|v| Instant::now() + v);
+                        }
+                        let reading = until(head_deadline, reader.read(&mut buf));
+                        // a connection which didn't send anything yet can be
+                        // closed when the server shuts down
+                        let read = if data.is_empty() {
+                            let closing = async {
+                                shutdown.wait().await;
+                                None
+                            };
+                            first(reading, closing).await
+                        } else {
+                            reading.await
+                        };
+                        match read {
+                            Some(read) => read?,
+                            None if data.is_empty() => return Ok(()),
+                            None => {
+                                declare_error!(
+                                    &mut writer,
+                                    StatusCode::RequestTimeout,
+                                    &server.settings
+                                );
+                            }
+                        }
+                    }
+                };
+                if read == 0 {
+                    if served > 0 && data.is_empty() {
+                        return Ok(());
+                    }
+                    declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
                 }
+                data.extend_from_slice(&buf[..read]);
+            };
+            if let Some(status) = server.check_head(&data[..head_end], true) {
+                declare_error!(&mut writer, status, &server.settings);
             }
-        }
-        let body_len = headers
-            .get("content-length")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0);
-        let mut body_vec: Vec<u8>;
-        if body_len > 0 {
-            if body_remainder.len() < body_len {
-                let mut temp: Vec<u8> = vec![0; body_len - body_remainder.len()];
-                reader.read_exact(&mut temp[..]).await?;
-                body_vec = Vec::with_capacity(body_len);
-                body_vec.extend_from_slice(body_remainder);
-                body_vec.extend_from_slice(&temp[..]);
-                body = &body_vec[..];
-            } else {
-                body = body_remainder;
+            let (request_line, mut headers) =
+                match str::from_utf8(&data[..head_end]).map(parse_without_body) {
+                    Ok(Some(parsed)) => parsed,
+                    _ => {
+                        declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
+                    }
+                };
+            let body_start = head_end + 4;
+            let body_deadline = timeouts.body_read.map(|v| Instant::now() + v);
+            let limits = &server.settings.limits;
+            // the body limit applies to the decoded size of chunked bodies
+            let mut decoder = ChunkedDecoder::with_limits(limits.body_bytes, limits.header_bytes);
+            // a proxy could pick the other header to frame the body with
+            if headers.contains_key("transfer-encoding") && headers.contains_key("content-length") {
+                declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
             }
-        } else {
-            body = &[];
-        }
-        if let Some(request) = Request::parse(request_line, &headers, body) {
-            let request_line = &request.request_line;
-            let mut res = Response::new_empty();
-            // Detect http version and validate
-            let checker = Http::validate(&request);
-            if checker.is_malformed() {
-                declare_error!(writer, checker.err_code.unwrap());
+            let chunked = match headers.get("transfer-encoding") {
+                // the length can't be known if chunked isn't the last encoding
+                Some(encoding) if !is_chunked(encoding) => {
+                    declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
+                }
+                Some(_) => true,
+                None => false,
+            };
+            let consumed = if chunked {
+                loop {
+                    match decoder.decode(&data[body_start..]) {
+                        DecodeState::Done(len) => break body_start + len,
+                        DecodeState::Malformed => {
+                            declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
+                        }
+                        DecodeState::TooLarge => {
+                            declare_error!(
+                                &mut writer,
+                                StatusCode::PayloadTooLarge,
+                                &server.settings
+                            );
+                        }
+                        DecodeState::Partial => {
+                            let read = match until(body_deadline, reader.read(&mut buf)).await {
+                                Some(read) => read?,
+                                None => {
+                                    declare_error!(
+                                        &mut writer,
+                                        StatusCode::RequestTimeout,
+                                        &server.settings
+                                    );
+                                }
+                            };
+                            if read == 0 {
+                                declare_error!(
+                                    &mut writer,
+                                    StatusCode::BadRequest,
+                                    &server.settings
+                                );
+                            }
+                            data.extend_from_slice(&buf[..read]);
+                        }
+                    }
+                }
+            } else {
+                let body_len = match headers.get("content-length").map(|v| content_length(v)) {
+                    Some(Some(len)) => len,
+                    Some(None) => {
+                        declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
+                    }
+                    None => 0,
+                };
+                if body_len > limits.body_bytes {
+                    declare_error!(&mut writer, StatusCode::PayloadTooLarge, &server.settings);
+                }
+                if data.len() < body_start + body_len {
+                    let read_till = data.len();
+                    data.resize(body_start + body_len, 0);
+                    let body = &mut data[read_till..];
+                    match until(body_deadline, reader.read_exact(body)).await {
+                        Some(read) => read?,
+                        None => {
+                            declare_error!(
+                                &mut writer,
+                                StatusCode::RequestTimeout,
+                                &server.settings
+                            );
+                        }
+                    };
+                }
+                body_start + body_len
+            };
+            if !decoder.trailers.is_empty() {
+                match Headers::parse(decoder.trailers.clone()) {
+                    Some(trailers) => headers.append_trailers(trailers),
+                    None => {
+                        declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
+                    }
+                }
             }
-            if request_line.method.is_some() {
+            {
+                let body = if chunked {
+                    &decoder.body[..]
+                } else {
+                    &data[body_start..consumed]
+                };
+                let request = match Request::parse(request_line, &headers, body) {
+                    Some(request) => request,
+                    None => {
+                        declare_error!(&mut writer, StatusCode::BadRequest, &server.settings);
+                    }
+                };
+                // Detect http version and validate
+                let checker = Http::validate(&request);
+                if checker.is_malformed() {
+                    declare_error!(&mut writer, checker.err_code.unwrap(), &server.settings);
+                }
+                if !server.router.implements(&request.request_line.method) {
+                    declare_error!(&mut writer, StatusCode::NotImplemented, &server.settings);
+                }
+                served += 1;
+                idle = server.keep_alive_for(&checker, served);
+
+                let mut res = Response::new_empty();
                 // run closures
-                server.router.run(request.clone(), &mut res);
+                let handler_deadline = timeouts.handler.map(|v| Instant::now() + v);
+                let run = server.router.run(request.clone(), &mut res);
+                if until(handler_deadline, run).await.is_none() {
+                    // whatever the closures did so far is dropped
+                    res = Response::new_empty();
+                    res.status(StatusCode::ServiceUnavailable);
+                }
                 if !res.has_body() {
-                    declare_error!(writer, StatusCode::NotFound);
+                    // closures which only set an error status get the
+                    // page of that status, everything else is a 404
+                    if !Error::is_error(res.status_code) {
+                        res = Response::new_empty();
+                        res.status(StatusCode::NotFound);
+                    }
+                    Error::render_page(res.status_code, &mut res, &server.settings);
+                }
+                // http 1.0 clients don't understand chunked bodies
+                if request.request_line.version == HttpVersion::Http10 {
+                    res.http_version(HttpVersion::Http10);
+                }
+                // responses to HEAD only have the headers of the body
+                let head = request.request_line.method == RequestMethod::Head;
+                // without a length the body can only end when the connection
+                // does, and no more requests are taken while shutting down
+                if !head && !res.is_delimited() || shutdown.is_shutting_down() {
+                    idle = None;
+                }
+                match idle {
+                    Some(duration) => {
+                        if request.request_line.version == HttpVersion::Http10 {
+                            res.set("Connection", "keep-alive");
+                            res.set("Keep-Alive", &format!("timeout={}", duration.as_secs()));
+                        }
+                    }
+                    None => {
+                        res.set("Connection", "close");
+                    }
                 }
 
-                Octane::send(res.get_data(), writer).await?;
-            } else {
-                declare_error!(writer, StatusCode::NotImplemented);
+                let mut response = res.get_data();
+                if head {
+                    response.1 = Box::new(empty());
+                }
+                let write_deadline = timeouts.write.map(|v| Instant::now() + v);
+                match until(write_deadline, Octane::send(response, &mut writer)).await {
+                    Some(sent) => sent?,
+                    // a client which doesn't read the response is dropped
+                    None => return Ok(()),
+                }
+            }
+            if idle.is_none() {
+                return Ok(());
             }
-        } else {
-            declare_error!(writer, StatusCode::BadRequest);
+            data.drain(..consumed);
         }
-        Ok(())
+    }
+    // Checks the request line and headers against the limits,
+    // head is everything read so far when it isn't complete yet
+    fn check_head(&self, head: &[u8], complete: bool) -> Option<StatusCode> {
+        let limits = &self.settings.limits;
+        let line_end = find_in_slice(head, B_CRLF).unwrap_or_else(|| head.len());
+        // the target comes after the first space of the request line
+        let uri_len = head[..line_end]
+            .split(|b| *b == b' ')
+            .nth(1)
+            .map_or(0, <[u8]>::len);
+        if uri_len > limits.uri_length {
+            return Some(StatusCode::UriTooLong);
+        }
+        if head.len() > limits.header_bytes {
+            return Some(StatusCode::RequestHeaderFieldsTooLarge);
+        }
+        // every header is preceded by a line break
+        if complete && head.windows(2).filter(|v| *v == B_CRLF).count() > limits.header_count {
+            return Some(StatusCode::RequestHeaderFieldsTooLarge);
+        }
+        None
+    }
+    // Decides how long the connection should be kept open for the
+    // next request after serving one, None means it should be closed
+    fn keep_alive_for(&self, checker: &Http, served: u64) -> Option<Duration> {
+        let configured = self.settings.keep_alive?;
+        if checker.max_requests.map_or(false, |max| served >= max) {
+            return None;
+        }
+        match checker.keep_alive {
+            KeepAliveState::Close => None,
+            KeepAliveState::UserDefined => Some(configured),
+            KeepAliveState::Particular(duration) => Some(duration.min(configured)),
+        }
+        .filter(|duration| *duration > Duration::from_secs(0))
     }
     pub(crate) async fn send<S>(
         mut response: (String, BoxReader),
@@ -275,6 +912,7 @@ impl Octane {
     {
         stream_async.write_all(response.0.as_bytes()).await?;
         copy(&mut response.1, &mut stream_async).await?;
+        stream_async.flush().await?;
         Ok(())
     }
 }
@@ -282,33 +920,54 @@ impl Octane {
 default!(Octane);
 
 impl Route for Octane {
-    fn head(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn head(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.head(path, closure)
     }
-    fn put(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn put(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.put(path, closure)
     }
-    fn get(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn get(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.get(path, closure)
     }
-    fn delete(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn delete(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.delete(path, closure)
     }
-    fn post(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn post(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.post(path, closure)
     }
-    fn patch(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn patch(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.patch(path, closure)
     }
-    fn add(&mut self, closure: Closure) -> RouterResult {
-        self.router.middlewares.push(Closures {
-            closure,
-            index: self.router.route_counter,
-        });
-        self.router.route_counter += 1;
-        Ok(())
+    fn options(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
+        self.router.options(path, closure)
+    }
+    fn trace(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
+        self.router.trace(path, closure)
+    }
+    fn connect(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
+        self.router.connect(path, closure)
+    }
+    fn route(
+        &mut self,
+        method: RequestMethod,
+        path: &str,
+        closure: impl Into<Handler>,
+    ) -> RouterResult {
+        self.router.route(method, path, closure)
+    }
+    fn route_named(
+        &mut self,
+        name: &str,
+        method: RequestMethod,
+        path: &str,
+        closure: impl Into<Handler>,
+    ) -> RouterResult {
+        self.router.route_named(name, method, path, closure)
     }
-    fn add_route(&mut self, path: &str, closure: Closure) -> RouterResult {
+    fn add(&mut self, closure: impl Into<Handler>) -> RouterResult {
+        self.router.add(closure)
+    }
+    fn add_route(&mut self, path: &str, closure: impl Into<Handler>) -> RouterResult {
         self.router.add_route(path, closure)
     }
 }
@@ -325,4 +984,298 @@ impl Config for Octane {
         self.settings.ssl.port = port;
         &mut self.settings.ssl
     }
+    fn error_page(&mut self, status: StatusCode, page: ErrorPage) {
+        self.settings.error_page(status, page);
+    }
+    fn fallback_error_page(&mut self, page: ErrorPage) {
+        self.settings.fallback_error_page(page);
+    }
+    fn limits(&mut self) -> &mut Limits {
+        &mut self.settings.limits
+    }
+    fn timeouts(&mut self) -> &mut Timeouts {
+        &mut self.settings.timeouts
+    }
+    fn socket(&mut self) -> &mut SocketOptions {
+        &mut self.settings.socket
+    }
+}
+
+// Parses the value of a Content-Length header, a repeated header
+// is only accepted when every value is the same
+fn content_length(value: &str) -> Option<usize> {
+    let mut lengths = value.split(',').map(|v| {
+        let v = v.trim();
+        // parse would take a sign too
+        if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        v.parse::<usize>().ok()
+    });
+    let first = lengths.next()??;
+    for length in lengths {
+        if length? != first {
+            return None;
+        }
+    }
+    Some(first)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{route_async, route_stop, task};
+    use tokio::io::duplex;
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+    use tokio::time::sleep;
+
+    // Writes the raw request to a connection served by the app and
+    // returns everything sent back until the server closes it
+    async fn exchange(app: Octane, request: &[u8]) -> String {
+        let (handle, shutdown) = ShutdownHandle::new();
+        let (mut client, stream) = duplex(1 << 20);
+        let server = Arc::new(app);
+        let serving = task!(Octane::serve(stream, server, shutdown).await.ok());
+        client.write_all(request).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        serving.await.unwrap();
+        drop(handle);
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    // Connects to the server, which might not be listening yet
+    async fn connect(addr: SocketAddr) -> TcpStream {
+        loop {
+            if let Ok(stream) = TcpStream::connect(addr).await {
+                return stream;
+            }
+            sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    fn echo() -> Octane {
+        let mut app = Octane::new();
+        app.post(
+            "/",
+            route_stop!(|req, res| {
+                res.send(req.body);
+            }),
+        )
+        .unwrap();
+        app
+    }
+
+    #[test]
+    fn success_content_length() {
+        // Repeated lengths are only fine when they are the same.
+        assert_eq!(content_length("5"), Some(5));
+        assert_eq!(content_length("5, 5"), Some(5));
+        assert_eq!(content_length("5, 6"), None);
+        assert_eq!(content_length("+5"), None);
+        assert_eq!(content_length("abc"), None);
+        assert_eq!(content_length(""), None);
+    }
+
+    #[crate::test]
+    async fn fail_ambiguous_framing() {
+        // Bodies which can't be framed for sure should close the connection.
+        for head in [
+            "Content-Length: abc",
+            "Content-Length: 5\r\nContent-Length: 6",
+            "Content-Length: 5\r\nTransfer-Encoding: chunked",
+        ]
+        .iter()
+        {
+            let request = format!(
+                "POST / HTTP/1.1\r\nHost: x\r\n{}\r\n\r\nhelloGET / HTTP/1.1\r\nHost: x\r\n\r\n",
+                head
+            );
+            let response = exchange(echo(), request.as_bytes()).await;
+            assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+            assert_eq!(response.matches("HTTP/1.1").count(), 1);
+        }
+        // The same length twice is still a single body.
+        let request = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+        let response = exchange(echo(), request).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("hello"));
+    }
+
+    #[crate::test]
+    async fn fail_signed_chunk_size() {
+        // Chunk sizes should only be hex digits.
+        let request = b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n+5\r\nhello\r\n0\r\n\r\n";
+        let response = exchange(echo(), request).await;
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    }
+
+    #[crate::test]
+    async fn fail_body_too_large() {
+        // Bodies over the limit should get a 413, however they are framed.
+        let requests: [&[u8]; 2] = [
+            b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 9\r\n\r\n123456789",
+            b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n4\r\nworl\r\n0\r\n\r\n",
+        ];
+        for request in requests.iter() {
+            let mut app = echo();
+            app.limits().body_bytes(8);
+            let response = exchange(app, request).await;
+            assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+        }
+        // The limit is on the decoded size of chunked bodies.
+        let mut app = echo();
+        app.limits().body_bytes(8);
+        let request = b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n4\r\nhell\r\n1\r\no\r\n1\r\n \r\n2\r\nyo\r\n0\r\n\r\n";
+        let response = exchange(app, request).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("hello yo"));
+    }
+
+    #[crate::test]
+    async fn fail_uri_too_long() {
+        // Request targets over the limit should get a 414.
+        let mut app = echo();
+        app.limits().uri_length(8);
+        let response = exchange(app, b"POST /0123456789 HTTP/1.1\r\nHost: x\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 414"), "{}", response);
+    }
+
+    #[crate::test]
+    async fn fail_head_too_large() {
+        // Too many header bytes should get a 431.
+        let mut app = echo();
+        app.limits().header_bytes(64);
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: x\r\nX-Big: {}\r\n\r\n",
+            "a".repeat(64)
+        );
+        let response = exchange(app, request.as_bytes()).await;
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+        // So should too many headers.
+        let mut app = echo();
+        app.limits().header_count(2);
+        let request = b"POST / HTTP/1.1\r\nHost: x\r\nX-A: 1\r\nX-B: 2\r\n\r\n";
+        let response = exchange(app, request).await;
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+    }
+
+    #[crate::test]
+    async fn fail_slow_head() {
+        // Requests which don't finish their head in time should get a 408.
+        let mut app = echo();
+        app.timeouts().header_read(Duration::from_millis(20));
+        let response = exchange(app, b"POST / HTTP/1.1\r\nHost: x").await;
+        assert!(response.starts_with("HTTP/1.1 408"), "{}", response);
+    }
+
+    #[crate::test]
+    async fn fail_slow_body() {
+        // Requests which don't finish their body in time should get a 408.
+        let mut app = echo();
+        app.timeouts().body_read(Duration::from_millis(20));
+        let request = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhel";
+        let response = exchange(app, request).await;
+        assert!(response.starts_with("HTTP/1.1 408"), "{}", response);
+    }
+
+    #[crate::test]
+    async fn fail_slow_handler() {
+        // Closures which take too long should get a 503.
+        let mut app = Octane::new();
+        app.get(
+            "/",
+            route_async!(|req, res| {
+                sleep(Duration::from_secs(5)).await;
+                res.send("late");
+                Flow::Stop
+            }),
+        )
+        .unwrap();
+        app.timeouts().handler(Duration::from_millis(20));
+        let request = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+        let response = exchange(app, request).await;
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        assert!(!response.contains("late"));
+    }
+
+    #[crate::test]
+    async fn success_idle_keep_alive() {
+        // Idle keep alive connections should be closed silently.
+        let mut app = echo();
+        app.set_keepalive(Duration::from_millis(20));
+        let request = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello";
+        let started = Instant::now();
+        let response = exchange(app, request).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(!response.contains("Connection: close"));
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[crate::test]
+    async fn success_shutdown() {
+        // Requests being handled should finish before the server stops.
+        let mut app = Octane::new();
+        app.get(
+            "/slow",
+            route_async!(|req, res| {
+                sleep(Duration::from_millis(50)).await;
+                res.send("done");
+                Flow::Stop
+            }),
+        )
+        .unwrap();
+        app.timeouts().shutdown(Duration::from_millis(200));
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (stop, signal) = oneshot::channel::<()>();
+        let serving = task!(app.listen_with_shutdown(addr, signal).await.is_ok());
+        let mut slow = connect(addr).await;
+        let mut stuck = connect(addr).await;
+        stuck
+            .write_all(b"GET /slow HTTP/1.1\r\nHost")
+            .await
+            .unwrap();
+        slow.write_all(b"GET /slow HTTP/1.1\r\nHost: x\r\n\r\n")
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(10)).await;
+        stop.send(()).unwrap();
+        let mut response = String::new();
+        slow.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("Connection: close"));
+        assert!(response.ends_with("done"));
+        // Connections still open after the timeout should be dropped.
+        let started = Instant::now();
+        let mut rest = Vec::new();
+        stuck.read_to_end(&mut rest).await.ok();
+        assert!(rest.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(serving.await.unwrap());
+    }
+
+    #[crate::test]
+    async fn success_serve_on() {
+        // Listeners bound beforehand should be served until the shutdown.
+        let app = echo();
+        let listener = app.bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, signal) = oneshot::channel::<()>();
+        let serving = task!(app.serve_on_with_shutdown(listener, signal).await.is_ok());
+        let mut stream = connect(addr).await;
+        let request =
+            b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+        stream.write_all(request).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("hello"));
+        stop.send(()).unwrap();
+        assert!(serving.await.unwrap());
+    }
 }
@@ -1,70 +1,319 @@
+use crate::config::SocketOptions;
 use crate::server::Octane;
 use crate::task;
+use crate::util::{first, until};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+#[cfg(unix)]
+use std::fs;
 use std::future::Future;
-use std::io::Result;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::stream::StreamExt;
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::stream::{Stream, StreamExt, StreamMap};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
 #[cfg(feature = "openSSL")]
 use tokio_openssl::SslStream;
 #[cfg(feature = "rustls")]
 use tokio_rustls::server::TlsStream;
 
 pub struct ServerBuilder {
-    socket: TcpListener,
+    sockets: Vec<TcpListener>,
+}
+
+// Listens on a unix domain socket, the socket file is removed
+// once it is dropped
+#[cfg(unix)]
+pub struct UnixServerBuilder {
+    socket: UnixListener,
+    path: PathBuf,
+}
+
+/// ToListenAddrs is implemented for the types the server can
+/// listen on, which are a port (listened on on every IPv4
+/// interface), the types which implement
+/// [`ToSocketAddrs`](https://doc.rust-lang.org/std/net/trait.ToSocketAddrs.html)
+/// and lists of them. A host name is listened on on every
+/// address it resolves to.
+///
+/// # Example
+///
+/// ```no_run
+/// use octane::Octane;
+/// use std::net::{Ipv6Addr, SocketAddr};
+///
+/// #[octane::main]
+/// async fn main() {
+///     let app = Octane::new();
+///     // IPv4 connections are accepted on [::] too, unless the
+///     // only_v6 socket option is set
+///     let addrs = vec![
+///         SocketAddr::from(([127, 0, 0, 1], 8080)),
+///         SocketAddr::from((Ipv6Addr::UNSPECIFIED, 8081)),
+///     ];
+///     app.listen(addrs, || println!("Server started"))
+///         .await
+///         .expect("Cannot establish connection");
+/// }
+/// ```
+pub trait ToListenAddrs {
+    /// Returns the addresses to listen on
+    fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>>;
+}
+
+impl ToListenAddrs for u16 {
+    fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>> {
+        Ok(vec![SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            *self,
+        )])
+    }
+}
+
+macro_rules! to_listen_addrs {
+    ( $( $type : ty ),* ) => {
+        $(
+            impl ToListenAddrs for $type {
+                fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>> {
+                    Ok(self.to_socket_addrs()?.collect())
+                }
+            }
+        )*
+    };
+}
+
+to_listen_addrs!(
+    SocketAddr,
+    SocketAddrV4,
+    SocketAddrV6,
+    (IpAddr, u16),
+    (Ipv4Addr, u16),
+    (Ipv6Addr, u16),
+    (&str, u16),
+    (String, u16),
+    str,
+    String
+);
+
+impl<T: ToListenAddrs + ?Sized> ToListenAddrs for &T {
+    fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>> {
+        (**self).to_listen_addrs()
+    }
+}
+
+impl<T: ToListenAddrs> ToListenAddrs for [T] {
+    fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>> {
+        let mut addrs = Vec::new();
+        for addr in self {
+            addrs.extend(addr.to_listen_addrs()?);
+        }
+        Ok(addrs)
+    }
+}
+
+impl<T: ToListenAddrs, const N: usize> ToListenAddrs for [T; N] {
+    fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>> {
+        self[..].to_listen_addrs()
+    }
+}
+
+impl<T: ToListenAddrs> ToListenAddrs for Vec<T> {
+    fn to_listen_addrs(&self) -> Result<Vec<SocketAddr>> {
+        self[..].to_listen_addrs()
+    }
+}
+
+// Lets the listeners and the connections know when the server is
+// shutting down, every connection holds on to one so the server
+// can wait for them to finish
+#[derive(Clone)]
+pub struct Shutdown {
+    signal: watch::Receiver<bool>,
+    // set once the server stops waiting for the connections
+    abort: watch::Receiver<bool>,
+    _alive: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    pub fn is_shutting_down(&self) -> bool {
+        *self.signal.borrow()
+    }
+    // Finishes once the server starts shutting down
+    pub async fn wait(&self) {
+        wait_for(&self.signal).await;
+    }
+    // Runs the connection until it's done, it is dropped if it
+    // is still open when the server stops waiting for it
+    pub async fn abortable<F: Future>(&self, connection: F) {
+        let running = async {
+            connection.await;
+        };
+        first(running, wait_for(&self.abort)).await;
+    }
+}
+
+// Finishes once the value is set, or once the server is gone
+// if the sender was dropped
+async fn wait_for(signal: &watch::Receiver<bool>) {
+    let mut signal = signal.clone();
+    while !*signal.borrow() {
+        if signal.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+// The side of the shutdown channel which is kept by the server
+pub struct ShutdownHandle {
+    signal: watch::Sender<bool>,
+    abort: watch::Sender<bool>,
+    alive: mpsc::Receiver<()>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> (Self, Shutdown) {
+        let (signal_tx, signal_rx) = watch::channel(false);
+        let (abort_tx, abort_rx) = watch::channel(false);
+        let (alive_tx, alive_rx) = mpsc::channel(1);
+        (
+            ShutdownHandle {
+                signal: signal_tx,
+                abort: abort_tx,
+                alive: alive_rx,
+            },
+            Shutdown {
+                signal: signal_rx,
+                abort: abort_rx,
+                _alive: alive_tx,
+            },
+        )
+    }
+    // Tells the listeners and the connections to stop and waits
+    // until every connection is closed or the time runs out, the
+    // connections left are dropped then
+    pub async fn shutdown(mut self, shutdown: Shutdown, timeout: Option<Duration>) {
+        self.signal.send(true).ok();
+        drop(shutdown);
+        let deadline = timeout.map(|v| Instant::now() + v);
+        // nothing is ever sent, recv only returns once every sender is dropped
+        if until(deadline, self.alive.recv()).await.is_none() {
+            self.abort.send(true).ok();
+        }
+    }
 }
 
 impl ServerBuilder {
-    pub fn new(port: u16) -> Result<Self> {
-        let stream = Type::stream();
-        let socket = Socket::new(Domain::ipv4(), stream.non_blocking(), Some(Protocol::tcp()))?;
-        let bind_add = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
-        socket.bind(&SockAddr::from(bind_add))?;
-        socket.listen(2048)?;
+    pub fn new(addrs: &[SocketAddr], options: &SocketOptions) -> Result<Self> {
+        if addrs.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "No address to listen on",
+            ));
+        }
+        let sockets = addrs
+            .iter()
+            .map(|addr| Self::bind(*addr, options))
+            .collect::<Result<_>>()?;
+        Self::from_std(sockets)
+    }
+
+    pub fn from_std(sockets: Vec<std::net::TcpListener>) -> Result<Self> {
+        let sockets = sockets
+            .into_iter()
+            .map(|socket| {
+                socket.set_nonblocking(true)?;
+                TcpListener::from_std(socket)
+            })
+            .collect::<Result<_>>()?;
+        Ok(ServerBuilder { sockets })
+    }
+
+    pub fn bind(addr: SocketAddr, options: &SocketOptions) -> Result<std::net::TcpListener> {
+        let domain = if addr.is_ipv6() {
+            Domain::ipv6()
+        } else {
+            Domain::ipv4()
+        };
+        let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+        // the options only apply if they are set before binding
         socket.set_reuse_address(true)?;
-        Ok(ServerBuilder {
-            socket: TcpListener::from_std(socket.into_tcp_listener())?,
-        })
+        if options.reuse_port {
+            #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+            socket.set_reuse_port(true)?;
+            #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+            return Err(Error::new(
+                ErrorKind::Other,
+                "SO_REUSEPORT isn't supported on this platform",
+            ));
+        }
+        if addr.is_ipv6() {
+            socket.set_only_v6(options.only_v6)?;
+        }
+        socket.bind(&SockAddr::from(addr))?;
+        socket.listen(options.backlog)?;
+        Ok(socket.into_tcp_listener())
     }
 
-    pub async fn listen<C, T>(mut self, exec: C, server: Arc<Octane>) -> Result<()>
+    // Merges the connections of every socket into one stream
+    fn incoming(self) -> StreamMap<usize, TcpListener> {
+        let mut incoming = StreamMap::new();
+        for (n, socket) in self.sockets.into_iter().enumerate() {
+            incoming.insert(n, socket);
+        }
+        incoming
+    }
+
+    pub async fn listen<C, T>(self, exec: C, server: Arc<Octane>, shutdown: Shutdown) -> Result<()>
     where
         T: Future + Send,
-        C: FnOnce(TcpStream, Arc<Octane>) -> T + Send + 'static + Copy,
+        C: FnOnce(TcpStream, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
     {
-        while let Some(stream) = self.socket.next().await {
-            stream.map(|stream| {
-                let server = Arc::clone(&server);
-                task!({
-                    exec(stream, server).await;
-                })
-            })?;
-        }
-        Ok(())
+        let incoming = self.incoming().map(|(_, stream)| stream);
+        accept(incoming, exec, server, shutdown).await
     }
 
     #[cfg(feature = "openSSL")]
-    pub async fn listen_ssl<C, T>(self, exec: C, server: Arc<Octane>) -> Result<()>
+    pub async fn listen_ssl<C, T>(
+        self,
+        exec: C,
+        server: Arc<Octane>,
+        shutdown: Shutdown,
+    ) -> Result<()>
     where
         T: Future + Send,
-        C: FnOnce(SslStream<TcpStream>, Arc<Octane>) -> T + Send + 'static + Copy,
+        C: FnOnce(SslStream<TcpStream>, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
     {
-        let mut ssl_listener = self.socket;
+        let mut ssl_listener = self.incoming();
         let acceptor = crate::tls::openssl::acceptor(&server.settings)?;
-        while let Some(stream) = ssl_listener.next().await {
+        while let Some((_, stream)) = first(ssl_listener.next(), async {
+            shutdown.wait().await;
+            None
+        })
+        .await
+        {
             let acceptor = acceptor.clone();
             let tcp_stream = stream?;
             let server = Arc::clone(&server);
+            let shutdown = shutdown.clone();
 
             task!({
-                let stream = tokio_openssl::accept(&acceptor, tcp_stream).await;
-                if let Ok(stream_ssl) = stream {
-                    exec(stream_ssl, server).await;
-                } else {
-                    stream.map_err(|e| println!("{:?}", e)).err();
-                }
+                let connection = shutdown.clone();
+                connection
+                    .abortable(async move {
+                        let stream = tokio_openssl::accept(&acceptor, tcp_stream).await;
+                        if let Ok(stream_ssl) = stream {
+                            exec(stream_ssl, server, shutdown).await;
+                        } else {
+                            stream.map_err(|e| println!("{:?}", e)).err();
+                        }
+                    })
+                    .await;
             });
         }
         Ok(())
@@ -75,30 +324,220 @@ impl ServerBuilder {
         self,
         exec: C,
         server: Arc<Octane>,
+        shutdown: Shutdown,
     ) -> std::result::Result<(), Box<dyn std::error::Error>>
     where
         T: Future + Send,
-        C: FnOnce(TlsStream<TcpStream>, Arc<Octane>) -> T + Send + 'static + Copy,
+        C: FnOnce(TlsStream<TcpStream>, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
     {
-        let mut ssl_listener = self.socket;
+        let mut ssl_listener = self.incoming();
         let acceptor = crate::tls::rustls::acceptor(&server.settings)?;
 
-        while let Some(stream) = ssl_listener.next().await {
+        while let Some((_, stream)) = first(ssl_listener.next(), async {
+            shutdown.wait().await;
+            None
+        })
+        .await
+        {
             let acceptor = acceptor.clone();
 
             let server = Arc::clone(&server);
+            let shutdown = shutdown.clone();
             let tcp_stream = stream?;
             task!({
-                let stream = acceptor.accept(tcp_stream).await;
-                if let Ok(stream_ssl) = stream {
-                    exec(stream_ssl, server).await;
-                } else {
-                    stream
-                        .map_err(|e| println!("WARNING: {:?}", e.kind()))
-                        .err();
-                }
+                let connection = shutdown.clone();
+                connection
+                    .abortable(async move {
+                        let stream = acceptor.accept(tcp_stream).await;
+                        if let Ok(stream_ssl) = stream {
+                            exec(stream_ssl, server, shutdown).await;
+                        } else {
+                            stream
+                                .map_err(|e| println!("WARNING: {:?}", e.kind()))
+                                .err();
+                        }
+                    })
+                    .await;
             });
         }
         Ok(())
     }
 }
+
+#[cfg(unix)]
+impl UnixServerBuilder {
+    pub fn new(path: &Path, options: &SocketOptions) -> Result<Self> {
+        Self::remove_stale(path)?;
+        let socket = Socket::new(Domain::unix(), Type::stream().non_blocking(), None)?;
+        socket.bind(&SockAddr::unix(path)?)?;
+        // the permissions are set before listening so nobody can
+        // connect before they apply
+        let listen = || {
+            if let Some(mode) = options.unix_mode {
+                use std::os::unix::fs::PermissionsExt;
+
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+            socket.listen(options.backlog)?;
+            UnixListener::from_std(socket.into_unix_listener())
+        };
+        match listen() {
+            Ok(socket) => Ok(UnixServerBuilder {
+                socket,
+                path: path.to_path_buf(),
+            }),
+            Err(e) => {
+                fs::remove_file(path).ok();
+                Err(e)
+            }
+        }
+    }
+
+    // A socket file left behind by a server which didn't exit
+    // cleanly refuses connections and is removed, anything else
+    // at the path is left alone
+    fn remove_stale(path: &Path) -> Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if !metadata.file_type().is_socket() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ));
+        }
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("A server is already listening on {}", path.display()),
+            )),
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => fs::remove_file(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn listen<C, T>(
+        mut self,
+        exec: C,
+        server: Arc<Octane>,
+        shutdown: Shutdown,
+    ) -> Result<()>
+    where
+        T: Future + Send,
+        C: FnOnce(UnixStream, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
+    {
+        accept(&mut self.socket, exec, server, shutdown).await
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixServerBuilder {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+// Runs exec on every connection until the server shuts down
+async fn accept<L, S, C, T>(
+    mut incoming: L,
+    exec: C,
+    server: Arc<Octane>,
+    shutdown: Shutdown,
+) -> Result<()>
+where
+    L: Stream<Item = Result<S>> + Unpin,
+    S: Send + 'static,
+    T: Future + Send,
+    C: FnOnce(S, Arc<Octane>, Shutdown) -> T + Send + 'static + Copy,
+{
+    // stop accepting once the server is shutting down
+    while let Some(stream) = first(incoming.next(), async {
+        shutdown.wait().await;
+        None
+    })
+    .await
+    {
+        stream.map(|stream| {
+            let server = Arc::clone(&server);
+            let shutdown = shutdown.clone();
+            task!({
+                let connection = shutdown.clone();
+                connection.abortable(exec(stream, server, shutdown)).await;
+            })
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn success_listen_addrs() {
+        // Ports should listen on every interface and lists should be flattened.
+        assert_eq!(
+            8080.to_listen_addrs().unwrap(),
+            vec![SocketAddr::from(([0, 0, 0, 0], 8080))]
+        );
+        let addrs = ["127.0.0.1:80", "[::1]:81"].to_listen_addrs().unwrap();
+        assert_eq!(
+            addrs,
+            vec![
+                SocketAddr::from(([127, 0, 0, 1], 80)),
+                SocketAddr::from((Ipv6Addr::LOCALHOST, 81))
+            ]
+        );
+        let addrs = vec![(Ipv4Addr::LOCALHOST, 82)].to_listen_addrs().unwrap();
+        assert_eq!(addrs, vec![SocketAddr::from(([127, 0, 0, 1], 82))]);
+    }
+
+    #[test]
+    fn fail_no_addrs() {
+        // Listening on nothing should be an error.
+        let error = ServerBuilder::new(&[], &SocketOptions::new())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[crate::test]
+    async fn success_bind_free_port() {
+        // Port 0 should get a free port which can be listened on.
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let listener = ServerBuilder::bind(addr, &SocketOptions::new()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        let server_builder = ServerBuilder::from_std(vec![listener]).unwrap();
+        assert_eq!(server_builder.sockets[0].local_addr().unwrap(), addr);
+    }
+
+    #[cfg(unix)]
+    #[crate::test]
+    async fn success_unix_socket() {
+        // Stale socket files should be replaced and removed on drop.
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("octane-{}.sock", std::process::id()));
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let mut options = SocketOptions::new();
+        options.unix_mode(0o600);
+        let server_builder = UnixServerBuilder::new(&path, &options).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // A live socket shouldn't be taken over.
+        let error = UnixServerBuilder::new(&path, &options).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AddrInUse);
+        drop(server_builder);
+        assert!(!path.exists());
+        // Neither should other files.
+        fs::write(&path, "").unwrap();
+        let error = UnixServerBuilder::new(&path, &options).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use crate::default;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// State is a map of values keyed by their type, it holds the
+/// application state that is handed over to the closures. Only
+/// one value of each type can be stored, so wrap values in a
+/// newtype if you need more than one of the same type.
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+///
+/// struct Greeting(&'static str);
+///
+/// let mut app = Octane::new();
+/// app.manage(Greeting("Hello, World"));
+/// app.get(
+///     "/",
+///     route!(|req, res| {
+///         let greeting = req.state::<Greeting>().unwrap();
+///         res.send(greeting.0);
+///         Flow::Stop
+///     }),
+/// );
+/// ```
+pub struct State {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl State {
+    /// Returns a new and empty State instance
+    pub fn new() -> Self {
+        State {
+            values: HashMap::new(),
+        }
+    }
+    /// Stores the value, returning the value of the same
+    /// type which was stored before if there was one
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|v| v.downcast().ok())
+            .map(|v| *v)
+    }
+    /// Returns a reference to the value of the type T if
+    /// one was stored
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_ref())
+    }
+    /// Returns true if a value of the type T is stored
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+    /// Returns the number of values stored
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    /// Returns true if no values are stored
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    // Merges the values of another State into self, the values
+    // which are already present in self are kept
+    pub(crate) fn append(&mut self, state: Self) {
+        for (id, value) in state.values.into_iter() {
+            self.values.entry(id).or_insert(value);
+        }
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+default!(State);
+
+/// Extensions is a map of values keyed by their type which only
+/// lives as long as the request, a middleware can insert values
+/// in it and the closures which run after it can read them. The
+/// closures only get a shared reference to the request so the
+/// values are kept behind an `Arc`.
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+/// use octane::responder::StatusCode;
+///
+/// struct User {
+///     name: String,
+/// }
+///
+/// let mut app = Octane::new();
+/// app.add(route_next!(|req, res| {
+///     if let Some(name) = req.headers.get("x-user") {
+///         req.extensions().insert(User { name: name.clone() });
+///     }
+/// }));
+/// app.get(
+///     "/",
+///     route!(|req, res| {
+///         match req.extensions().get::<User>() {
+///             Some(user) => res.send(format!("Hello, {}", user.name)),
+///             None => res.status(StatusCode::Unauthorized).send("Who are you?"),
+///         };
+///         Flow::Stop
+///     }),
+/// );
+/// ```
+pub struct Extensions {
+    values: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl Extensions {
+    /// Returns a new and empty Extensions instance
+    pub fn new() -> Self {
+        Extensions {
+            values: Mutex::new(HashMap::new()),
+        }
+    }
+    /// Stores the value, returning the value of the same
+    /// type which was stored before if there was one
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) -> Option<Arc<T>> {
+        self.lock()
+            .insert(TypeId::of::<T>(), Arc::new(value))
+            .and_then(|v| v.downcast().ok())
+    }
+    /// Returns the value of the type T if one was stored
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.lock()
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|v| v.downcast().ok())
+    }
+    /// Removes the value of the type T and returns it
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.lock()
+            .remove(&TypeId::of::<T>())
+            .and_then(|v| v.downcast().ok())
+    }
+    /// Returns true if a value of the type T is stored
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.lock().contains_key(&TypeId::of::<T>())
+    }
+    // A closure panicking while holding the lock can't leave the
+    // map in a broken state so poisoning is ignored
+    fn lock(&self) -> MutexGuard<'_, HashMap<TypeId, Arc<dyn Any + Send + Sync>>> {
+        self.values.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clone for Extensions {
+    fn clone(&self) -> Self {
+        Extensions {
+            values: Mutex::new(self.lock().clone()),
+        }
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.lock().len())
+            .finish()
+    }
+}
+
+default!(Extensions);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Pool(usize);
+
+    #[test]
+    fn success_insert_get() {
+        // Values should be retrieved by their type.
+        let mut state = State::new();
+        assert_eq!(state.insert(Pool(1)), None);
+        assert_eq!(state.insert(String::from("config")), None);
+        assert_eq!(state.get::<Pool>(), Some(&Pool(1)));
+        assert_eq!(state.get::<String>().unwrap(), "config");
+        assert_eq!(state.get::<u8>(), None);
+        // Inserting the same type should replace the value.
+        assert_eq!(state.insert(Pool(2)), Some(Pool(1)));
+        assert_eq!(state.get::<Pool>(), Some(&Pool(2)));
+        assert_eq!(state.len(), 2);
+    }
+
+    #[test]
+    fn success_append() {
+        // Existing values should take precedence over appended ones.
+        let mut first = State::new();
+        first.insert(Pool(1));
+        let mut second = State::new();
+        second.insert(Pool(2));
+        second.insert(5_u32);
+        first.append(second);
+        assert_eq!(first.get::<Pool>(), Some(&Pool(1)));
+        assert_eq!(first.get::<u32>(), Some(&5));
+    }
+
+    #[test]
+    fn success_extensions() {
+        // Values should be insertable through a shared reference.
+        let extensions = Extensions::new();
+        assert!(extensions.insert(Pool(1)).is_none());
+        assert_eq!(*extensions.get::<Pool>().unwrap(), Pool(1));
+        let cloned = extensions.clone();
+        assert_eq!(*extensions.insert(Pool(2)).unwrap(), Pool(1));
+        assert_eq!(*cloned.get::<Pool>().unwrap(), Pool(1));
+        assert_eq!(*extensions.remove::<Pool>().unwrap(), Pool(2));
+        assert!(!extensions.contains::<Pool>());
+    }
+}
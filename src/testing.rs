@@ -0,0 +1,386 @@
+use crate::constants::BUF_SIZE;
+#[cfg(feature = "cookies")]
+use crate::cookie::Cookie;
+use crate::http::chunked::{is_chunked, ChunkedDecoder, DecodeState};
+use crate::request::{Headers, RequestMethod};
+use crate::server::Octane;
+use crate::server_builder::ShutdownHandle;
+use crate::task;
+use crate::util::find_in_slice;
+#[cfg(feature = "cookies")]
+use cookie::Cookie as CookieRs;
+use octane_json::Value;
+use std::io::{Error, ErrorKind, Result};
+use std::str;
+use std::sync::Arc;
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt};
+
+/// TestClient runs requests through the app without a network,
+/// the request is written to an in-memory stream which the
+/// server reads from just like it would from a socket, so the
+/// whole app (limits, timeouts, error pages and so on) is tested.
+/// It needs to run inside a tokio runtime like the one of
+/// [`#[octane::test]`](../attr.test.html).
+///
+/// # Example
+///
+/// ```
+/// use octane::prelude::*;
+/// use octane::testing::TestClient;
+///
+/// #[octane::main]
+/// async fn main() {
+///     let mut app = Octane::new();
+///     app.get(
+///         "/",
+///         route_stop!(|req, res| {
+///             res.send("Hello, World");
+///         }),
+///     )
+///     .unwrap();
+///     let client = TestClient::new(app);
+///     let res = client.get("/").send().await.unwrap();
+///     assert_eq!(res.status, 200);
+///     assert_eq!(res.text(), "Hello, World");
+/// }
+/// ```
+pub struct TestClient {
+    server: Arc<Octane>,
+}
+
+impl TestClient {
+    /// Returns a new TestClient which sends its requests to the app
+    pub fn new(app: Octane) -> Self {
+        TestClient {
+            server: Arc::new(app),
+        }
+    }
+    /// Starts a request with the method and path given, the
+    /// path can have a query string
+    pub fn request(&self, method: RequestMethod, path: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            method,
+            path: path.to_owned(),
+            headers: Vec::new(),
+            cookies: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+    /// Starts a GET request
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Get, path)
+    }
+    /// Starts a POST request
+    pub fn post(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Post, path)
+    }
+    /// Starts a PUT request
+    pub fn put(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Put, path)
+    }
+    /// Starts a PATCH request
+    pub fn patch(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Patch, path)
+    }
+    /// Starts a DELETE request
+    pub fn delete(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Delete, path)
+    }
+    /// Starts a HEAD request
+    pub fn head(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Head, path)
+    }
+    /// Starts an OPTIONS request
+    pub fn options(&self, path: &str) -> TestRequest<'_> {
+        self.request(RequestMethod::Options, path)
+    }
+}
+
+/// TestRequest is a request which is being built by a
+/// [`TestClient`](struct.TestClient.html), it is sent with
+/// [`send()`](#method.send)
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    method: RequestMethod,
+    path: String,
+    headers: Vec<(String, String)>,
+    cookies: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl TestRequest<'_> {
+    /// Adds a header to the request, a `Host` header is sent if
+    /// none is added
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+    /// Adds a cookie to the `Cookie` header of the request
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.push((name.to_owned(), value.to_owned()));
+        self
+    }
+    /// Sets the body of the request, its `Content-Length` is
+    /// sent along with it unless a `Content-Length` or a
+    /// `Transfer-Encoding` header was added
+    pub fn body<T: Into<Vec<u8>>>(mut self, body: T) -> Self {
+        self.body = body.into();
+        self
+    }
+    /// Sends the request and waits for the whole response, an
+    /// error is returned if the response can't be parsed
+    pub async fn send(self) -> Result<TestResponse> {
+        let data = self.serialise();
+        let (client, stream) = duplex(BUF_SIZE);
+        // the handle has to live until the connection is done or
+        // the server would think it is shutting down
+        let (handle, shutdown) = ShutdownHandle::new();
+        let server = Arc::clone(&self.client.server);
+        task!({
+            Octane::serve(stream, server, shutdown).await.ok();
+        });
+        // the server can answer before reading the whole request
+        // so the request is written while the response is read
+        let (mut reader, mut writer) = split(client);
+        let writing = task!({
+            writer.write_all(&data).await.ok();
+            writer
+        });
+        let mut response = Vec::new();
+        reader.read_to_end(&mut response).await?;
+        writing.await.ok();
+        drop(handle);
+        TestResponse::parse(&response)
+    }
+    // Writes the request the same way a client would, the
+    // connection is closed by the server after the response
+    fn serialise(&self) -> Vec<u8> {
+        let has_header = |header: &str| {
+            self.headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(header))
+        };
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method.as_str(), self.path);
+        if !has_header("host") {
+            head.push_str("Host: localhost\r\n");
+        }
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !self.cookies.is_empty() {
+            let cookies: Vec<String> = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            head.push_str(&format!("Cookie: {}\r\n", cookies.join("; ")));
+        }
+        // the body is framed by the caller if they set the headers
+        if !self.body.is_empty()
+            && !has_header("content-length")
+            && !has_header("transfer-encoding")
+        {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        let mut data = head.into_bytes();
+        data.extend_from_slice(&self.body);
+        data
+    }
+}
+
+/// TestResponse is the response the server sent to a
+/// [`TestRequest`](struct.TestRequest.html)
+#[derive(Debug)]
+pub struct TestResponse {
+    /// The status code, like 200
+    pub status: i32,
+    /// The reason phrase sent after the status code, like "OK"
+    pub reason: String,
+    /// The headers, the names are lowercase
+    pub headers: Headers,
+    /// The cookies which were set with `Set-Cookie` headers
+    #[cfg(feature = "cookies")]
+    pub cookies: Vec<Cookie<'static>>,
+    /// The body, decoded if it was chunked
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// Returns the value of the header with the name given,
+    /// case insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .parsed
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str())
+    }
+    /// Returns the cookie with the name given
+    #[cfg(feature = "cookies")]
+    pub fn cookie(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.cookies.iter().find(|cookie| cookie.name() == name)
+    }
+    /// Returns the body as a string, invalid utf-8 is replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+    /// Parses the body as json
+    pub fn json(&self) -> Option<Value> {
+        Value::parse(str::from_utf8(&self.body).ok()?)
+    }
+    fn parse(data: &[u8]) -> Result<Self> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_owned());
+        let head_end =
+            find_in_slice(data, b"\r\n\r\n").ok_or_else(|| invalid("Incomplete response head"))?;
+        let head =
+            str::from_utf8(&data[..head_end]).map_err(|_| invalid("Response head isn't utf-8"))?;
+        let (line, rest) = match head.find("\r\n") {
+            Some(n) => (&head[..n], &head[n + 2..]),
+            None => (head, ""),
+        };
+        let mut toks = line.splitn(3, ' ');
+        toks.next();
+        let status = toks
+            .next()
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| invalid("Invalid status line"))?;
+        let reason = toks.next().unwrap_or("").to_owned();
+        let headers =
+            Headers::parse(rest.to_owned()).ok_or_else(|| invalid("Invalid response headers"))?;
+        let mut body = data[head_end + 4..].to_vec();
+        if headers
+            .parsed
+            .get("transfer-encoding")
+            .map_or(false, |v| is_chunked(v))
+        {
+            let mut decoder = ChunkedDecoder::new();
+            match decoder.decode(&body) {
+                DecodeState::Done(_) => body = decoder.body,
+                _ => return Err(invalid("Invalid chunked body")),
+            }
+        }
+        // cookies are parsed from the lines since the values of
+        // the headers with the same name are joined
+        #[cfg(feature = "cookies")]
+        let cookies = rest
+            .split("\r\n")
+            .filter_map(|header| {
+                let (name, value) = header.split_at(header.find(':')?);
+                if !name.trim().eq_ignore_ascii_case("set-cookie") {
+                    return None;
+                }
+                CookieRs::parse(value[1..].trim().to_owned())
+                    .ok()
+                    .map(Cookie::from)
+            })
+            .collect();
+        Ok(TestResponse {
+            status,
+            reason,
+            headers,
+            #[cfg(feature = "cookies")]
+            cookies,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::responder::StatusCode;
+    use crate::route;
+    #[cfg(feature = "cookies")]
+    use crate::route_stop;
+    use crate::router::{Flow, Route};
+
+    #[crate::test]
+    async fn success_test_client() {
+        // Requests should go through the whole app.
+        let mut app = Octane::new();
+        app.post(
+            "/echo",
+            route!(|req, res| {
+                let name = req.headers.get("x-name").cloned().unwrap_or_default();
+                res.status(StatusCode::Created)
+                    .set("x-name", &name)
+                    .send(req.body);
+                Flow::Stop
+            }),
+        )
+        .unwrap();
+        let client = TestClient::new(app);
+        let res = client
+            .post("/echo")
+            .header("X-Name", "octane")
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status, 201);
+        assert_eq!(res.reason, "CREATED");
+        assert_eq!(res.header("X-Name"), Some("octane"));
+        assert_eq!(res.text(), "hello");
+        // Unknown routes should get the error page.
+        let res = client.get("/missing").send().await.unwrap();
+        assert_eq!(res.status, 404);
+    }
+
+    #[crate::test]
+    async fn success_test_client_framing() {
+        // Bodies framed by the caller shouldn't get a second length.
+        let mut app = Octane::new();
+        app.post(
+            "/",
+            route!(|req, res| {
+                res.send(req.body);
+                Flow::Stop
+            }),
+        )
+        .unwrap();
+        let client = TestClient::new(app);
+        let request = client.post("/").header("Content-Length", "5").body("hello");
+        let data = String::from_utf8(request.serialise()).unwrap();
+        assert_eq!(
+            data.to_ascii_lowercase().matches("content-length").count(),
+            1
+        );
+        assert_eq!(request.send().await.unwrap().text(), "hello");
+        let res = client
+            .post("/")
+            .header("Transfer-Encoding", "chunked")
+            .body("5\r\nhello\r\n0\r\n\r\n")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.text(), "hello");
+    }
+
+    #[cfg(feature = "cookies")]
+    #[crate::test]
+    async fn success_test_client_cookies() {
+        // Cookies should be sent and parsed back.
+        let mut app = Octane::new();
+        app.get(
+            "/",
+            route_stop!(|req, res| {
+                let cookies = &req.request.cookies;
+                let session = cookies.iter().find(|c| c.name() == "session").unwrap();
+                let seen = CookieRs::new("seen", session.value().to_owned());
+                res.cookie(Cookie::from(seen)).send("");
+            }),
+        )
+        .unwrap();
+        let client = TestClient::new(app);
+        let res = client
+            .get("/")
+            .cookie("session", "abc")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.cookie("seen").unwrap().value(), "abc");
+    }
+}
@@ -1,10 +1,7 @@
 #![cfg(feature = "openSSL")]
 use crate::config::OctaneConfig;
-use crate::tls::AsMutStream;
 use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 use std::io::Result;
-use tokio::net::TcpStream;
-use tokio_openssl::SslStream;
 
 pub fn acceptor(settings: &OctaneConfig) -> Result<SslAcceptor> {
     let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
@@ -13,9 +10,3 @@ pub fn acceptor(settings: &OctaneConfig) -> Result<SslAcceptor> {
     let acceptor = acceptor.build();
     Ok(acceptor)
 }
-
-impl AsMutStream for SslStream<TcpStream> {
-    fn stream_mut(&mut self) -> &mut TcpStream {
-        self.get_mut()
-    }
-}
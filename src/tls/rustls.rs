@@ -1,10 +1,7 @@
 #![cfg(feature = "rustls")]
 use crate::config::OctaneConfig;
-use crate::tls::AsMutStream;
 use std::error::Error;
 use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio_rustls::server::TlsStream;
 use tokio_rustls::{
     rustls::{NoClientAuth, ServerConfig},
     TlsAcceptor,
@@ -23,9 +20,3 @@ pub fn acceptor(settings: &OctaneConfig) -> Result<TlsAcceptor, Box<dyn Error>>
     let acceptor = TlsAcceptor::from(Arc::new(config));
     Ok(acceptor)
 }
-
-impl AsMutStream for TlsStream<TcpStream> {
-    fn stream_mut(&mut self) -> &mut TcpStream {
-        self.get_mut().0
-    }
-}
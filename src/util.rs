@@ -1,9 +1,11 @@
+use std::future::Future;
 use std::io::Read;
 use std::iter::FusedIterator;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::io::{AsyncRead, ReadBuf, Result};
+use tokio::time::{timeout_at, Instant};
 
 pub fn find_in_slice<T: Eq>(haystack: &[T], needle: &[T]) -> Option<usize> {
     // naive algorithm only meant for small needles
@@ -25,6 +27,40 @@ pub fn find_in_slice<T: Eq>(haystack: &[T], needle: &[T]) -> Option<usize> {
     None
 }
 
+// Runs the future until the deadline, returns None if the
+// deadline passed first
+pub async fn until<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+// Runs both futures and resolves to the output of the one
+// which finishes first, the other one is dropped
+pub fn first<A, B>(a: A, b: B) -> First<A, B> {
+    First {
+        a: Box::pin(a),
+        b: Box::pin(b),
+    }
+}
+
+pub struct First<A, B> {
+    a: Pin<Box<A>>,
+    b: Pin<Box<B>>,
+}
+
+impl<T, A: Future<Output = T>, B: Future<Output = T>> Future for First<A, B> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if let Poll::Ready(v) = self.a.as_mut().poll(cx) {
+            return Poll::Ready(v);
+        }
+        self.b.as_mut().poll(cx)
+    }
+}
+
 pub struct Spliterator<'a, T: Eq> {
     pub string: &'a [T],
     pub finished: bool,
@@ -83,7 +119,8 @@ impl<T: Read + Unpin> Deref for AsyncReader<T> {
 
 impl<T: Read + Unpin> AsyncRead for AsyncReader<T> {
     fn poll_read(mut self: Pin<&mut Self>, _: &mut Context, buf: &mut ReadBuf) -> Poll<Result<()>> {
-        self.reader.read(buf.initialized_mut())?;
+        let read = self.reader.read(buf.initialize_unfilled())?;
+        buf.advance(read);
         Poll::Ready(Ok(()))
     }
 }
@@ -93,3 +130,19 @@ impl<T: Read + Unpin> AsyncReader<T> {
         Self { reader }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use tokio::io::AsyncReadExt;
+
+    #[crate::test]
+    async fn success_async_reader() {
+        // Everything read should end up in the buffer.
+        let mut reader = AsyncReader::new(Cursor::new(b"hello world".to_vec()));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"hello world");
+    }
+}
@@ -1,12 +1,13 @@
 use octane::Octane;
 use std::future::Future;
+use std::net::SocketAddr;
 use tokio::runtime::Builder;
+use tokio::sync::oneshot;
 
-pub const PORT: u16 = 8080;
-
+// Every server gets its own free port so the tests can run in parallel
 pub fn run<T, F>(octane: Octane, exec: T)
 where
-    T: Fn() -> F,
+    T: Fn(SocketAddr) -> F,
     F: Future,
 {
     let mut builder = Builder::new_multi_thread();
@@ -15,19 +16,27 @@ where
     let runtime = builder.build().expect("Unable to build tokio runtime");
 
     runtime.block_on(async {
+        let listener = octane.bind("127.0.0.1:0").expect("Unable to bind");
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
         let handle = tokio::spawn(async {
-            octane.listen(PORT, || {}).await.unwrap();
+            octane
+                .serve_on_with_shutdown(listener, async {
+                    stopped.await.ok();
+                })
+                .await
+                .unwrap();
         });
-        exec().await;
+        exec(addr).await;
+        stop.send(()).ok();
         handle.await.unwrap();
     });
 }
 
 #[macro_export]
 macro_rules! path {
-    ( $url : expr ) => {{
-        use common::PORT;
-        format!("{}/{}", format!("http://0.0.0.0:{}", PORT), $url)
+    ( $addr : expr, $url : expr ) => {{
+        format!("http://{}/{}", $addr, $url)
     }};
 }
 
@@ -13,10 +13,10 @@ pub fn basic_header_value_retrival() {
         }),
     )
     .unwrap();
-    common::run(app, || async {
+    common::run(app, |addr| async move {
         let client = reqwest::Client::new();
         client
-            .get(&path!(""))
+            .get(&path!(addr, ""))
             .header(USER_AGENT, string)
             .send()
             .await
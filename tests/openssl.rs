@@ -16,7 +16,7 @@ pub fn basic_https_hello_world_openssl() {
         }),
     )
     .unwrap();
-    common::run(app, || async {
+    common::run(app, |_| async move {
         let client = ClientBuilder::new()
             .danger_accept_invalid_certs(true)
             .build()
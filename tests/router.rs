@@ -37,12 +37,12 @@ pub fn basic_router() {
     app.head("/route2", route_next!(|req, res| res.send(string)))
         .unwrap();
     app.with_router(router);
-    common::run(app, || async {
+    common::run(app, |addr| async move {
         let client = reqwest::Client::new();
         assert_eq!(
             string,
             client
-                .get(&path!("route1"))
+                .get(&path!(addr, "route1"))
                 .send()
                 .await
                 .unwrap()
@@ -53,7 +53,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .get(&path!("route2"))
+                .get(&path!(addr, "route2"))
                 .send()
                 .await
                 .unwrap()
@@ -64,7 +64,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .post(&path!("route1"))
+                .post(&path!(addr, "route1"))
                 .send()
                 .await
                 .unwrap()
@@ -75,7 +75,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .post(&path!("route2"))
+                .post(&path!(addr, "route2"))
                 .send()
                 .await
                 .unwrap()
@@ -86,7 +86,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .patch(&path!("route1"))
+                .patch(&path!(addr, "route1"))
                 .send()
                 .await
                 .unwrap()
@@ -97,7 +97,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .patch(&path!("route2"))
+                .patch(&path!(addr, "route2"))
                 .send()
                 .await
                 .unwrap()
@@ -108,7 +108,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .put(&path!("route1"))
+                .put(&path!(addr, "route1"))
                 .send()
                 .await
                 .unwrap()
@@ -119,7 +119,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .put(&path!("route2"))
+                .put(&path!(addr, "route2"))
                 .send()
                 .await
                 .unwrap()
@@ -130,7 +130,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .delete(&path!("route1"))
+                .delete(&path!(addr, "route1"))
                 .send()
                 .await
                 .unwrap()
@@ -141,7 +141,7 @@ pub fn basic_router() {
         assert_eq!(
             string,
             client
-                .delete(&path!("route2"))
+                .delete(&path!(addr, "route2"))
                 .send()
                 .await
                 .unwrap()
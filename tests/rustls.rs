@@ -19,7 +19,7 @@ pub fn basic_https_hello_world_rustls() {
         }),
     )
     .unwrap();
-    common::run(app, || async {
+    common::run(app, |_| async move {
         let mut buf = Vec::new();
         File::open("templates/cert.pem")
             .unwrap()
@@ -10,8 +10,8 @@ pub fn basic_server_hello() {
         }),
     )
     .unwrap();
-    common::run(app, || async {
-        assert_eq!("Hello, World", common::client_request(&path!("")).await);
+    common::run(app, |addr| async move {
+        assert_eq!("Hello, World", common::client_request(&path!(addr, "")).await);
     });
 }
 
@@ -60,12 +60,12 @@ pub fn basic_server_x_req() {
         }),
     )
     .unwrap();
-    common::run(app, || async {
+    common::run(app, |addr| async move {
         let client = reqwest::Client::new();
         assert_eq!(
             string,
             client
-                .get(&path!(""))
+                .get(&path!(addr, ""))
                 .send()
                 .await
                 .unwrap()
@@ -76,7 +76,7 @@ pub fn basic_server_x_req() {
         assert_eq!(
             string,
             client
-                .post(&path!(""))
+                .post(&path!(addr, ""))
                 .send()
                 .await
                 .unwrap()
@@ -87,7 +87,7 @@ pub fn basic_server_x_req() {
         assert_eq!(
             string,
             client
-                .put(&path!(""))
+                .put(&path!(addr, ""))
                 .send()
                 .await
                 .unwrap()
@@ -98,7 +98,7 @@ pub fn basic_server_x_req() {
         assert_eq!(
             string,
             client
-                .patch(&path!(""))
+                .patch(&path!(addr, ""))
                 .send()
                 .await
                 .unwrap()
@@ -109,7 +109,7 @@ pub fn basic_server_x_req() {
         assert_eq!(
             string,
             client
-                .delete(&path!(""))
+                .delete(&path!(addr, ""))
                 .send()
                 .await
                 .unwrap()
@@ -120,7 +120,7 @@ pub fn basic_server_x_req() {
         assert_eq!(
             string,
             client
-                .head(&path!(""))
+                .head(&path!(addr, ""))
                 .send()
                 .await
                 .unwrap()
@@ -13,7 +13,7 @@ pub fn basic_url_variables() {
         }),
     )
     .unwrap();
-    common::run(app, || async {
-        common::client_request(&path!(format!("foo/{}", value))).await;
+    common::run(app, |addr| async move {
+        common::client_request(&path!(addr, format!("foo/{}", value))).await;
     });
 }